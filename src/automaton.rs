@@ -0,0 +1,397 @@
+//! Evaluation of path queries as automata.
+//!
+//! Nesting combinators for closures over alternations revisits the same
+//! node at the same point of the query many times over. Compiling the
+//! query to a nondeterministic automaton instead, and searching the
+//! product of the graph with the automaton, visits each pair of a node
+//! and an automaton state at most once, so evaluation is polynomial in
+//! the size of the graph and the query.
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::{Direction, Kleisli, PathExpr};
+
+struct State<L> {
+    eps: Vec<usize>,
+    edges: Vec<(L, Direction, usize)>,
+}
+
+// Bounds the size of an automaton, which has a copy of `p` for each
+// repetition `p{n,m}` may take
+const MAX_STATES: usize = 1 << 20;
+
+struct Nfa<L> {
+    states: Vec<State<L>>,
+    start: usize,
+    accept: usize,
+}
+
+impl<L: Clone> Nfa<L> {
+    fn new(expr: &PathExpr<L>) -> Result<Self, AutomatonTooLarge> {
+        let mut nfa = Nfa {
+            states: Vec::new(),
+            start: 0,
+            accept: 0,
+        };
+        let (start, accept) = nfa.fragment(expr, Direction::Forward)?;
+        nfa.start = start;
+        nfa.accept = accept;
+        Ok(nfa)
+    }
+
+    fn state(&mut self) -> Result<usize, AutomatonTooLarge> {
+        if self.states.len() == MAX_STATES {
+            return Err(AutomatonTooLarge);
+        }
+        self.states.push(State {
+            eps: Vec::new(),
+            edges: Vec::new(),
+        });
+        Ok(self.states.len() - 1)
+    }
+
+    fn eps(&mut self, from: usize, to: usize) {
+        self.states[from].eps.push(to);
+    }
+
+    // Thompson's construction: the entry and exit states of an
+    // automaton for `expr` followed in the direction `dir`.
+    fn fragment(
+        &mut self,
+        expr: &PathExpr<L>,
+        dir: Direction,
+    ) -> Result<(usize, usize), AutomatonTooLarge> {
+        match expr {
+            PathExpr::Empty => {
+                let s = self.state()?;
+                Ok((s, s))
+            }
+            PathExpr::Edge(label) => {
+                let (s, t) = (self.state()?, self.state()?);
+                self.states[s].edges.push((label.clone(), dir, t));
+                Ok((s, t))
+            }
+            PathExpr::Inverse(p) => self.fragment(p, dir.reverse()),
+            PathExpr::Seq(..) => {
+                let mut steps = expr.operands();
+                if dir == Direction::Backward {
+                    steps.reverse();
+                }
+                let s = self.state()?;
+                let mut end = s;
+                for step in steps {
+                    let (ps, pt) = self.fragment(step, dir)?;
+                    self.eps(end, ps);
+                    end = pt;
+                }
+                Ok((s, end))
+            }
+            PathExpr::Alt(..) => {
+                let (s, t) = (self.state()?, self.state()?);
+                for branch in expr.operands() {
+                    let (ps, pt) = self.fragment(branch, dir)?;
+                    self.eps(s, ps);
+                    self.eps(pt, t);
+                }
+                Ok((s, t))
+            }
+            PathExpr::Star(p) => self.closure(p, dir, true),
+            PathExpr::Plus(p) => self.closure(p, dir, false),
+            PathExpr::Opt(p) => self.repeat(p, dir, 0, Some(1)),
+            PathExpr::Repeat { expr, min, max } => self.repeat(expr, dir, *min, *max),
+        }
+    }
+
+    fn closure(
+        &mut self,
+        p: &PathExpr<L>,
+        dir: Direction,
+        reflexive: bool,
+    ) -> Result<(usize, usize), AutomatonTooLarge> {
+        let (s, t) = (self.state()?, self.state()?);
+        let (ps, pt) = self.fragment(p, dir)?;
+        self.eps(s, ps);
+        self.eps(pt, ps);
+        self.eps(pt, t);
+        if reflexive {
+            self.eps(s, t);
+        }
+        Ok((s, t))
+    }
+
+    // `min` copies of `p` in sequence, then `max - min` optional ones,
+    // or a closure of `p` when there is no upper bound.
+    fn repeat(
+        &mut self,
+        p: &PathExpr<L>,
+        dir: Direction,
+        min: usize,
+        max: Option<usize>,
+    ) -> Result<(usize, usize), AutomatonTooLarge> {
+        let s = self.state()?;
+        if max.is_some_and(|max| max < min) {
+            // nothing matches, so the exit is unreachable
+            return Ok((s, self.state()?));
+        }
+        let mut end = s;
+        for _ in 0..min {
+            let (ps, pt) = self.fragment(p, dir)?;
+            self.eps(end, ps);
+            end = pt;
+        }
+        match max {
+            None => {
+                let (cs, ct) = self.closure(p, dir, true)?;
+                self.eps(end, cs);
+                end = ct;
+            }
+            Some(max) => {
+                let t = self.state()?;
+                self.eps(end, t);
+                for _ in min..max {
+                    let (ps, pt) = self.fragment(p, dir)?;
+                    self.eps(end, ps);
+                    self.eps(pt, t);
+                    end = pt;
+                }
+                end = t;
+            }
+        }
+        Ok((s, end))
+    }
+}
+
+/// A path whose automaton would have more than `2^20` states, as
+/// repetitions with large bounds, especially nested ones, unroll to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutomatonTooLarge;
+
+impl fmt::Display for AutomatonTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "path automaton would have more than {MAX_STATES} states")
+    }
+}
+
+impl Error for AutomatonTooLarge {}
+
+/// A path query compiled to an automaton, evaluated as an arrow from
+/// each start node to the distinct nodes at the end of matching routes.
+pub struct PathAutomaton<L, E> {
+    nfa: Rc<Nfa<L>>,
+    lookup: Rc<RefCell<E>>,
+}
+
+impl<L, E> Clone for PathAutomaton<L, E> {
+    fn clone(&self) -> Self {
+        PathAutomaton {
+            nfa: self.nfa.clone(),
+            lookup: self.lookup.clone(),
+        }
+    }
+}
+
+/// Compiles a path expression to an automaton, taking neighbours from
+/// `lookup` as `compile_path` does. Unlike `compile_path`, every
+/// operator has set semantics: each end node is yielded once.
+///
+/// The automaton has a copy of `p` for each repetition `p{n,m}` may
+/// take, so it grows with the product of nested repetition bounds, and
+/// compilation fails once it would exceed `2^20` states.
+pub fn compile_path_automaton<N, L, E, I>(
+    expr: &PathExpr<L>,
+    lookup: E,
+) -> Result<PathAutomaton<L, E>, AutomatonTooLarge>
+where
+    N: Clone + Eq + Hash,
+    L: Clone,
+    E: FnMut(&L, Direction, N) -> I,
+    I: IntoIterator<Item = N>,
+{
+    Ok(PathAutomaton {
+        nfa: Rc::new(Nfa::new(expr)?),
+        lookup: Rc::new(RefCell::new(lookup)),
+    })
+}
+
+impl<N, L, E, I> Kleisli<N> for PathAutomaton<L, E>
+where
+    N: Clone + Eq + Hash,
+    E: FnMut(&L, Direction, N) -> I,
+    I: IntoIterator<Item = N>,
+{
+    type Output = N;
+    type Iter = ApplyPathAutomaton<N, L, E>;
+
+    fn apply(&mut self, a: N) -> Self::Iter {
+        let start = (a, self.nfa.start);
+        ApplyPathAutomaton {
+            automaton: self.clone(),
+            visited: HashSet::from([start.clone()]),
+            stack: vec![start],
+            found: HashSet::new(),
+        }
+    }
+}
+
+pub struct ApplyPathAutomaton<N, L, E> {
+    automaton: PathAutomaton<L, E>,
+    visited: HashSet<(N, usize)>,
+    stack: Vec<(N, usize)>,
+    found: HashSet<N>,
+}
+
+impl<N: Clone, L, E> Clone for ApplyPathAutomaton<N, L, E> {
+    fn clone(&self) -> Self {
+        ApplyPathAutomaton {
+            automaton: self.automaton.clone(),
+            visited: self.visited.clone(),
+            stack: self.stack.clone(),
+            found: self.found.clone(),
+        }
+    }
+}
+
+impl<N, L, E, I> Iterator for ApplyPathAutomaton<N, L, E>
+where
+    N: Clone + Eq + Hash,
+    E: FnMut(&L, Direction, N) -> I,
+    I: IntoIterator<Item = N>,
+{
+    type Item = N;
+
+    fn next(&mut self) -> Option<N> {
+        let nfa = &self.automaton.nfa;
+        while let Some((n, q)) = self.stack.pop() {
+            let state = &nfa.states[q];
+            for &r in &state.eps {
+                if self.visited.insert((n.clone(), r)) {
+                    self.stack.push((n.clone(), r));
+                }
+            }
+            for (label, dir, r) in &state.edges {
+                let mut lookup = self.automaton.lookup.borrow_mut();
+                for m in lookup(label, *dir, n.clone()) {
+                    if self.visited.insert((m.clone(), *r)) {
+                        self.stack.push((m, *r));
+                    }
+                }
+            }
+            if q == nfa.accept && self.found.insert(n.clone()) {
+                return Some(n);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_path, parse_path};
+    use std::cell::Cell;
+
+    const GRAPH: &[(u32, &str, u32)] = &[
+        (1, "a", 2),
+        (2, "a", 3),
+        (3, "a", 1),
+        (1, "b", 3),
+        (3, "b", 4),
+        (4, "c", 5),
+        (5, "c", 4),
+    ];
+
+    fn lookup(label: &String, dir: Direction, node: u32) -> Vec<u32> {
+        GRAPH
+            .iter()
+            .filter(|e| e.1 == label)
+            .filter_map(|&(s, _, o)| match dir {
+                Direction::Forward => (s == node).then_some(o),
+                Direction::Backward => (o == node).then_some(s),
+            })
+            .collect()
+    }
+
+    fn sorted<I: Iterator<Item = u32>>(it: I) -> Vec<u32> {
+        let mut v: Vec<_> = it.collect();
+        v.sort();
+        v.dedup();
+        v
+    }
+
+    #[test]
+    fn agrees_with_combinators() {
+        for src in [
+            "a",
+            "a/a",
+            "a|b",
+            "(a|b)*",
+            "a+/b",
+            "^a",
+            "^(a/b)",
+            "b?/c*",
+            "a{2}",
+            "a{1,2}/b",
+            "(a|^c){2,}",
+            "((a|b)*/c)+",
+            "^(b/c+)/a?",
+        ] {
+            let expr = parse_path(src).unwrap();
+            let mut automaton = compile_path_automaton(&expr, lookup).unwrap();
+            let mut combinators = compile_path(&expr, lookup);
+            for start in 1..=5 {
+                let res: Vec<_> = automaton.apply(start).collect();
+                let distinct: HashSet<_> = res.iter().collect();
+                assert_eq!(distinct.len(), res.len(), "{src} from {start}");
+                assert_eq!(
+                    sorted(res.into_iter()),
+                    sorted(combinators.apply(start)),
+                    "{src} from {start}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn inverted_bounds_match_nothing() {
+        let expr = PathExpr::edge("a".to_string()).repeat(3, Some(1));
+        assert_eq!(
+            compile_path_automaton(&expr, lookup)
+                .unwrap()
+                .apply(1)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn visits_each_node_and_state_once() {
+        let calls = Cell::new(0);
+        let counted = |label: &String, dir: Direction, node: u32| {
+            calls.set(calls.get() + 1);
+            lookup(label, dir, node)
+        };
+        let expr = parse_path("((a|b)*/(a|b)*)*").unwrap();
+        let mut automaton = compile_path_automaton(&expr, counted).unwrap();
+        let edges: usize = automaton.nfa.states.iter().map(|s| s.edges.len()).sum();
+        assert_eq!(sorted(automaton.apply(1)), vec![1, 2, 3, 4]);
+        assert!(calls.get() <= 5 * edges);
+    }
+
+    #[test]
+    fn rejects_automata_too_large() {
+        let size = |src: &str| {
+            let expr = parse_path(src).unwrap();
+            compile_path_automaton(&expr, lookup).map(|a| a.nfa.states.len())
+        };
+        assert!(size("a{0,70000}").is_ok());
+        assert!(size("(a{256}){256}").is_ok());
+        assert_eq!(size("((a/b){100}){4000,}"), Err(AutomatonTooLarge));
+
+        let expr = PathExpr::edge("a".to_string()).repeat(0, Some(50_000_000));
+        assert!(compile_path_automaton(&expr, lookup).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Arrows paired with their inverses.
+//!
+//! A `BiArrow` carries an arrow `A -> [B]` together with its inverse
+//! `B -> [A]`, so that a path can be followed from either end.
+//! Composition composes the forward arrows in order and the inverses in
+//! reverse order, keeping the pair consistent.
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{kleisli_compose, FanOut, Kleisli, KleisliCompose, Path};
+
+#[derive(Clone)]
+pub struct BiArrow<F, G> {
+    forward: F,
+    backward: G,
+    fan_out: Option<FanOut>,
+}
+
+impl<F, G> BiArrow<F, G> {
+    /// Pairs `forward` with `backward`, which must be its inverse:
+    /// `b` is among the results of `forward(a)` exactly when `a` is
+    /// among those of `backward(b)`.
+    pub fn new(forward: F, backward: G) -> Self {
+        BiArrow {
+            forward,
+            backward,
+            fan_out: None,
+        }
+    }
+
+    /// Attaches an estimate of how many results each direction yields
+    /// per input, for use by `plan_chain`.
+    pub fn with_fan_out(mut self, fan_out: FanOut) -> Self {
+        self.fan_out = Some(fan_out);
+        self
+    }
+
+    pub fn fan_out(&self) -> Option<FanOut> {
+        self.fan_out
+    }
+
+    /// The same relation followed the other way.
+    pub fn inverse(self) -> BiArrow<G, F> {
+        BiArrow {
+            forward: self.backward,
+            backward: self.forward,
+            fan_out: self.fan_out.map(FanOut::inverse),
+        }
+    }
+
+    pub fn forward(&mut self) -> &mut F {
+        &mut self.forward
+    }
+
+    pub fn backward(&mut self) -> &mut G {
+        &mut self.backward
+    }
+
+    pub fn into_parts(self) -> (F, G) {
+        (self.forward, self.backward)
+    }
+
+    /// Applies the inverse arrow, from the end of the path to its start.
+    pub fn apply_backward<B>(&mut self, b: B) -> G::Iter
+    where
+        G: Kleisli<B>,
+    {
+        self.backward.apply(b)
+    }
+}
+
+/// `f >=> h`, whose inverse is `h⁻¹ >=> f⁻¹`.
+pub fn bi_kleisli_compose<A, B, C, F, G, H, K>(
+    f: BiArrow<F, G>,
+    h: BiArrow<H, K>,
+) -> BiArrow<KleisliCompose<F, H>, KleisliCompose<K, G>>
+where
+    F: Kleisli<A, Output = B>,
+    G: Kleisli<B, Output = A>,
+    H: Kleisli<B, Output = C>,
+    K: Kleisli<C, Output = B>,
+{
+    BiArrow {
+        fan_out: f.fan_out.zip(h.fan_out).map(|(a, b)| a.then(b)),
+        forward: kleisli_compose(f.forward, h.forward),
+        backward: kleisli_compose(h.backward, f.backward),
+    }
+}
+
+/// Applying a `BiArrow` follows it forwards.
+impl<A, F: Kleisli<A>, G> Kleisli<A> for BiArrow<F, G> {
+    type Output = F::Output;
+    type Iter = F::Iter;
+
+    fn apply(&mut self, a: A) -> F::Iter {
+        self.forward.apply(a)
+    }
+}
+
+/// Finds every path `source = a0, a1, ..., an = target` with `a(i+1)`
+/// among the results of `path[i]` applied to `ai`.
+///
+/// Steps are taken alternately forward from `source` and backward from
+/// `target` until the two frontiers reach the same point in the chain,
+/// where they are joined on their common node.
+pub fn meet_in_middle<A, F, G>(source: A, target: A, path: &mut [BiArrow<F, G>]) -> Vec<Vec<A>>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+    G: Kleisli<A, Output = A>,
+{
+    // `ahead` paths run from the source, `behind` paths from the target
+    let mut ahead = vec![Path::new().push(source)];
+    let mut behind = vec![Path::new().push(target)];
+    let (mut i, mut j) = (0, path.len());
+    while i < j {
+        if ahead.is_empty() || behind.is_empty() {
+            return Vec::new();
+        }
+        if (path.len() - j) < i {
+            behind = expand(behind, &mut path[j - 1].backward);
+            j -= 1;
+        } else {
+            ahead = expand(ahead, &mut path[i].forward);
+            i += 1;
+        }
+    }
+
+    join(&ahead, &behind)
+}
+
+/// Joins paths from the start with paths from the end on their last
+/// node, indexing whichever side is smaller.
+pub(crate) fn join<A: Clone + Eq + Hash>(ahead: &[Path<A>], behind: &[Path<A>]) -> Vec<Vec<A>> {
+    let ahead_builds = ahead.len() <= behind.len();
+    let (build, probe) = if ahead_builds {
+        (ahead, behind)
+    } else {
+        (behind, ahead)
+    };
+    let mut index: HashMap<&A, Vec<&Path<A>>> = HashMap::new();
+    for p in build {
+        if let Some(a) = p.last() {
+            index.entry(a).or_default().push(p);
+        }
+    }
+    let mut found = Vec::new();
+    for p in probe {
+        let Some(matches) = p.last().and_then(|a| index.get(a)) else {
+            continue;
+        };
+        for m in matches {
+            let (head, tail) = if ahead_builds { (*m, p) } else { (p, *m) };
+            let mut full = head.to_vec();
+            full.extend(tail.iter_rev().skip(1).cloned());
+            found.push(full);
+        }
+    }
+    found
+}
+
+pub(crate) fn expand<A: Clone, F: Kleisli<A, Output = A>>(
+    frontier: Vec<Path<A>>,
+    f: &mut F,
+) -> Vec<Path<A>> {
+    let mut next = Vec::new();
+    for p in frontier {
+        if let Some(a) = p.last() {
+            next.extend(f.apply(a.clone()).map(|b| p.push(b)));
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENTS: &[(&str, &str)] = &[
+        ("ann", "bea"),
+        ("ann", "cal"),
+        ("bea", "dan"),
+        ("cal", "eve"),
+        ("cal", "fay"),
+    ];
+
+    fn child_of() -> BiArrow<
+        impl Fn(&'static str) -> Vec<&'static str> + Clone,
+        impl Fn(&'static str) -> Vec<&'static str> + Clone,
+    > {
+        BiArrow::new(
+            |p: &'static str| PARENTS.iter().filter(|e| e.0 == p).map(|e| e.1).collect(),
+            |c: &'static str| PARENTS.iter().filter(|e| e.1 == c).map(|e| e.0).collect(),
+        )
+    }
+
+    #[test]
+    fn composition_inverts_in_reverse_order() {
+        let mut grandchild = bi_kleisli_compose(child_of(), child_of());
+        assert_eq!(
+            grandchild.apply("ann").collect::<Vec<_>>(),
+            vec!["dan", "eve", "fay"]
+        );
+        assert_eq!(
+            grandchild.apply_backward("fay").collect::<Vec<_>>(),
+            vec!["ann"]
+        );
+
+        let mut grandparent = grandchild.inverse();
+        assert_eq!(grandparent.apply("eve").collect::<Vec<_>>(), vec!["ann"]);
+        assert_eq!(grandparent.apply_backward("ann").count(), 3);
+    }
+
+    #[test]
+    fn meets_between_bound_ends() {
+        let mut chain = vec![child_of(), child_of()];
+        assert_eq!(
+            meet_in_middle("ann", "eve", &mut chain),
+            vec![vec!["ann", "cal", "eve"]]
+        );
+        assert!(meet_in_middle("bea", "eve", &mut chain).is_empty());
+        assert_eq!(
+            meet_in_middle("ann", "ann", &mut chain[..0]),
+            vec![vec!["ann"]]
+        );
+    }
+
+    #[test]
+    fn meet_in_middle_expands_from_both_ends() {
+        use std::cell::RefCell;
+
+        // a ladder of width two: every node steps to both nodes of the next rung
+        let calls = RefCell::new(Vec::new());
+        let rung = || {
+            BiArrow::new(
+                |x: usize| {
+                    calls.borrow_mut().push(('f', x));
+                    vec![2 * (x / 2) + 2, 2 * (x / 2) + 3]
+                },
+                |x: usize| {
+                    calls.borrow_mut().push(('b', x));
+                    vec![2 * (x / 2) - 2, 2 * (x / 2) - 1]
+                },
+            )
+        };
+        let mut chain = vec![rung(), rung(), rung(), rung()];
+        let paths = meet_in_middle(0, 8, &mut chain);
+        assert_eq!(paths.len(), 8);
+        assert!(paths.iter().all(|p| p.len() == 5 && p[0] == 0 && p[4] == 8));
+        // two steps from each end instead of fanning out four steps forward
+        assert_eq!(
+            *calls.borrow(),
+            vec![('f', 0), ('b', 8), ('f', 2), ('f', 3), ('b', 6), ('b', 7)]
+        );
+    }
+
+    #[test]
+    fn mixed_directions() {
+        // siblings, including oneself: up to a parent and back down
+        let mut sibling = bi_kleisli_compose(child_of().inverse(), child_of());
+        assert_eq!(sibling.apply("eve").collect::<Vec<_>>(), vec!["eve", "fay"]);
+        assert_eq!(
+            sibling.apply_backward("dan").collect::<Vec<_>>(),
+            vec!["dan"]
+        );
+    }
+}
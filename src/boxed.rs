@@ -0,0 +1,54 @@
+//! Type erased Kleisli arrows.
+//!
+//! Arrows assembled at run time, such as compiled path queries, have no
+//! single static type. A `BoxKleisli` hides the arrow and its iterator
+//! behind trait objects while staying clonable, so it can be used as
+//! either argument of any combinator.
+use crate::Kleisli;
+
+trait DynKleisli<'a, A, B> {
+    fn apply_dyn(&mut self, a: A) -> Box<dyn Iterator<Item = B> + 'a>;
+    fn clone_dyn(&self) -> Box<dyn DynKleisli<'a, A, B> + 'a>;
+}
+
+impl<'a, A, B, K> DynKleisli<'a, A, B> for K
+where
+    K: Kleisli<A, Output = B> + Clone + 'a,
+    K::Iter: 'a,
+{
+    fn apply_dyn(&mut self, a: A) -> Box<dyn Iterator<Item = B> + 'a> {
+        Box::new(self.apply(a))
+    }
+
+    fn clone_dyn(&self) -> Box<dyn DynKleisli<'a, A, B> + 'a> {
+        Box::new(self.clone())
+    }
+}
+
+/// A boxed arrow `A -> [B]`.
+pub struct BoxKleisli<'a, A, B>(Box<dyn DynKleisli<'a, A, B> + 'a>);
+
+impl<'a, A, B> BoxKleisli<'a, A, B> {
+    pub fn new<K>(k: K) -> Self
+    where
+        K: Kleisli<A, Output = B> + Clone + 'a,
+        K::Iter: 'a,
+    {
+        BoxKleisli(Box::new(k))
+    }
+}
+
+impl<A, B> Clone for BoxKleisli<'_, A, B> {
+    fn clone(&self) -> Self {
+        BoxKleisli(self.0.clone_dyn())
+    }
+}
+
+impl<'a, A, B> Kleisli<A> for BoxKleisli<'a, A, B> {
+    type Output = B;
+    type Iter = Box<dyn Iterator<Item = B> + 'a>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        self.0.apply_dyn(a)
+    }
+}
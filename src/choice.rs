@@ -0,0 +1,250 @@
+//! Branching combinators: alternation of Kleisli arrows over the same
+//! input, yielding the results of each branch in turn.
+use crate::Kleisli;
+
+#[derive(Clone)]
+pub struct KleisliChoice<F, G> {
+    f: F,
+    g: G,
+}
+
+/// Alternation `f | g`: the results of `f(a)` followed by those of
+/// `g(a)`. `g` is only applied once `f(a)` is exhausted.
+pub fn kleisli_choice<A, F, G>(f: F, g: G) -> KleisliChoice<F, G>
+where
+    A: Clone,
+    F: Kleisli<A>,
+    G: Kleisli<A, Output = F::Output>,
+{
+    KleisliChoice { f, g }
+}
+
+/// Applying a choice clones the input for `f` and clones `g`, keeping
+/// both until `g` is needed.
+impl<A, F, G> Kleisli<A> for KleisliChoice<F, G>
+where
+    A: Clone,
+    F: Kleisli<A>,
+    G: Kleisli<A, Output = F::Output> + Clone,
+{
+    type Output = F::Output;
+    type Iter = ApplyKleisliChoice<A, F, G>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliChoice {
+            left: Some(self.f.apply(a.clone())),
+            pending: Some((a, self.g.clone())),
+            right: None,
+        }
+    }
+}
+
+pub struct ApplyKleisliChoice<A, F: Kleisli<A>, G: Kleisli<A>> {
+    left: Option<F::Iter>,
+    pending: Option<(A, G)>,
+    right: Option<G::Iter>,
+}
+
+impl<A, F, G> Clone for ApplyKleisliChoice<A, F, G>
+where
+    A: Clone,
+    F: Kleisli<A>,
+    G: Kleisli<A> + Clone,
+    F::Iter: Clone,
+    G::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliChoice {
+            left: self.left.clone(),
+            pending: self.pending.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+impl<A, F, G> ApplyKleisliChoice<A, F, G>
+where
+    A: Clone,
+    F: Kleisli<A>,
+    G: Kleisli<A, Output = F::Output>,
+{
+    pub fn new(a: A, choice: KleisliChoice<F, G>) -> Self {
+        let KleisliChoice { mut f, g } = choice;
+        ApplyKleisliChoice {
+            left: Some(f.apply(a.clone())),
+            pending: Some((a, g)),
+            right: None,
+        }
+    }
+}
+
+impl<A, F, G> Iterator for ApplyKleisliChoice<A, F, G>
+where
+    F: Kleisli<A>,
+    G: Kleisli<A, Output = F::Output>,
+{
+    type Item = F::Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(left) = &mut self.left {
+            if let Some(x) = left.next() {
+                return Some(x);
+            }
+            self.left = None;
+        }
+        if let Some((a, mut g)) = self.pending.take() {
+            self.right = Some(g.apply(a));
+        }
+        self.right.as_mut()?.next()
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliOr<F> {
+    arrows: Vec<F>,
+}
+
+/// N-ary alternation `f | g | h | ...` over arrows of the same type.
+/// An empty alternation yields nothing.
+pub fn kleisli_or<A, F, I>(arrows: I) -> KleisliOr<F>
+where
+    A: Clone,
+    F: Kleisli<A>,
+    I: IntoIterator<Item = F>,
+{
+    KleisliOr {
+        arrows: arrows.into_iter().collect(),
+    }
+}
+
+impl<A, F> Kleisli<A> for KleisliOr<F>
+where
+    A: Clone,
+    F: Kleisli<A> + Clone,
+{
+    type Output = F::Output;
+    type Iter = ApplyKleisliOr<A, F>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliOr::new(a, self.clone())
+    }
+}
+
+pub struct ApplyKleisliOr<A, F: Kleisli<A>> {
+    a: Option<A>,
+    arrows: std::vec::IntoIter<F>,
+    current: Option<F::Iter>,
+}
+
+impl<A, F> Clone for ApplyKleisliOr<A, F>
+where
+    A: Clone,
+    F: Kleisli<A> + Clone,
+    F::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliOr {
+            a: self.a.clone(),
+            arrows: self.arrows.clone(),
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<A, F> ApplyKleisliOr<A, F>
+where
+    A: Clone,
+    F: Kleisli<A>,
+{
+    pub fn new(a: A, or: KleisliOr<F>) -> Self {
+        ApplyKleisliOr {
+            a: Some(a),
+            arrows: or.arrows.into_iter(),
+            current: None,
+        }
+    }
+}
+
+impl<A, F> Iterator for ApplyKleisliOr<A, F>
+where
+    A: Clone,
+    F: Kleisli<A>,
+{
+    type Item = F::Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(current) = &mut self.current {
+                if let Some(x) = current.next() {
+                    return Some(x);
+                }
+            }
+            let mut f = self.arrows.next()?;
+            // the last branch can take the input itself
+            let a = if self.arrows.len() == 0 {
+                self.a.take()?
+            } else {
+                self.a.clone()?
+            };
+            self.current = Some(f.apply(a));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kleisli_compose;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn choice_yields_left_then_right() {
+        let mut k = kleisli_choice(|x: usize| vec![x + 1, x + 2], |x: usize| vec![x * 10]);
+        assert_eq!(k.apply(1).collect::<Vec<_>>(), vec![2, 3, 10]);
+    }
+
+    #[test]
+    fn choice_applies_right_lazily() {
+        let calls = Rc::new(Cell::new(0));
+        let counted = calls.clone();
+        let mut k = kleisli_choice(
+            |x: String| vec![x.clone()],
+            move |x: String| {
+                counted.set(counted.get() + 1);
+                vec![x + "!"]
+            },
+        );
+        let mut it = k.apply("a".to_string());
+        assert_eq!(it.next(), Some("a".to_string()));
+        assert_eq!(calls.get(), 0);
+        assert_eq!(it.next(), Some("a!".to_string()));
+        assert_eq!(calls.get(), 1);
+        assert_eq!(it.next(), None);
+    }
+
+    #[test]
+    fn forked_choice_resumes_both_branches() {
+        let mut it = kleisli_choice(|x: usize| vec![x], |x: usize| vec![x + 1, x + 2]).apply(1);
+        assert_eq!(it.next(), Some(1));
+        assert_eq!(it.next(), Some(2));
+        let fork = it.clone();
+        assert_eq!(it.collect::<Vec<_>>(), vec![3]);
+        assert_eq!(fork.collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn or_alternates_all_branches() {
+        let arrows: Vec<Box<dyn Fn(usize) -> Vec<usize>>> = vec![
+            Box::new(|x| vec![x]),
+            Box::new(|_| vec![]),
+            Box::new(|x| vec![x + 1, x + 2]),
+        ];
+        let branches = kleisli_or(arrows.iter().map(|f| move |x: usize| f(x)));
+        let mut k = kleisli_compose(|x: usize| vec![x, 10 * x], branches);
+        assert_eq!(k.apply(1).collect::<Vec<_>>(), vec![1, 2, 3, 10, 11, 12]);
+
+        let mut empty = kleisli_or(Vec::<fn(usize) -> Vec<usize>>::new());
+        assert_eq!(empty.apply(1).count(), 0);
+    }
+}
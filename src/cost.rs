@@ -0,0 +1,301 @@
+//! Cost-based evaluation of composition chains.
+//!
+//! A chain `f0 >=> f1 >=> ... >=> fn` of `BiArrow`s can be evaluated
+//! forward from its sources, backward from its targets, or from both ends
+//! at once, meeting at some step in between. Which is cheapest depends on
+//! how many results each step yields, so `plan_chain` estimates the work
+//! for every meeting point from fan-out statistics and picks the least.
+use std::hash::Hash;
+
+use crate::bidi::{expand, join};
+use crate::{BiArrow, Kleisli, Path};
+
+/// The estimated number of results per input of an arrow, in each
+/// direction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FanOut {
+    pub forward: f64,
+    pub backward: f64,
+}
+
+impl FanOut {
+    pub fn new(forward: f64, backward: f64) -> Self {
+        FanOut { forward, backward }
+    }
+
+    pub fn inverse(self) -> Self {
+        FanOut {
+            forward: self.backward,
+            backward: self.forward,
+        }
+    }
+
+    /// The fan-out of `self >=> next`.
+    pub fn then(self, next: FanOut) -> Self {
+        FanOut {
+            forward: self.forward * next.forward,
+            backward: self.backward * next.backward,
+        }
+    }
+}
+
+/// Used for steps without any estimate.
+impl Default for FanOut {
+    fn default() -> Self {
+        FanOut::new(1.0, 1.0)
+    }
+}
+
+/// Cardinality statistics for planning a chain.
+#[derive(Clone, Debug, Default)]
+pub struct ChainStats {
+    /// How many nodes the chain starts from, or `None` if the start is
+    /// unbound.
+    pub sources: Option<f64>,
+    /// How many nodes the chain must end at, or `None` if the end is
+    /// unbound.
+    pub targets: Option<f64>,
+    /// The fan-out of each step, overriding the estimate carried by the
+    /// arrow.
+    pub fan_out: Vec<Option<FanOut>>,
+}
+
+/// Where a chain is evaluated from: steps before the pivot are taken
+/// forward from the sources, the rest backward from the targets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainPlan {
+    pivot: usize,
+    cost: f64,
+}
+
+impl ChainPlan {
+    pub fn pivot(&self) -> usize {
+        self.pivot
+    }
+
+    /// The estimated number of intermediate results.
+    pub fn cost(&self) -> f64 {
+        self.cost
+    }
+}
+
+/// Picks the cheapest meeting point for `chain`, or `None` if neither
+/// end of the chain is bound, leaving nowhere to start from.
+pub fn plan_chain<F, G>(chain: &[BiArrow<F, G>], stats: &ChainStats) -> Option<ChainPlan> {
+    let fan_out: Vec<FanOut> = chain
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            stats
+                .fan_out
+                .get(i)
+                .copied()
+                .flatten()
+                .or(step.fan_out())
+                .unwrap_or_default()
+        })
+        .collect();
+    let n = chain.len();
+    let pivots = match (stats.sources, stats.targets) {
+        (Some(_), Some(_)) => 0..=n,
+        (Some(_), None) => n..=n,
+        (None, Some(_)) => 0..=0,
+        (None, None) => return None,
+    };
+
+    // `ahead[k]` and `behind[k]` estimate the frontier at step k when
+    // reached from the sources and from the targets
+    let mut ahead = vec![stats.sources.unwrap_or(0.0); n + 1];
+    for (i, f) in fan_out.iter().enumerate() {
+        ahead[i + 1] = ahead[i] * f.forward;
+    }
+    let mut behind = vec![stats.targets.unwrap_or(0.0); n + 1];
+    for (i, f) in fan_out.iter().enumerate().rev() {
+        behind[i] = behind[i + 1] * f.backward;
+    }
+
+    pivots
+        .map(|pivot| ChainPlan {
+            pivot,
+            cost: ahead[1..=pivot].iter().sum::<f64>()
+                + behind[pivot..n].iter().sum::<f64>()
+                + ahead[pivot]
+                + behind[pivot],
+        })
+        .min_by(|a, b| a.cost.total_cmp(&b.cost))
+}
+
+/// Evaluates `chain` according to `plan`, returning every path from one
+/// of `sources` to one of `targets`. An unbound end, `None`, matches any
+/// node, though with both ends unbound there is nothing to start from
+/// and nothing is found.
+///
+/// # Panics
+///
+/// If the pivot of the plan lies past the end of `chain`, as it can for
+/// a plan made for a shorter chain, or if the plan takes steps from an
+/// unbound end.
+pub fn evaluate_chain<A, F, G>(
+    chain: &mut [BiArrow<F, G>],
+    plan: &ChainPlan,
+    sources: Option<&[A]>,
+    targets: Option<&[A]>,
+) -> Vec<Vec<A>>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+    G: Kleisli<A, Output = A>,
+{
+    assert!(
+        plan.pivot <= chain.len(),
+        "plan pivot {} is past the end of a chain of {} steps",
+        plan.pivot,
+        chain.len()
+    );
+    if sources.is_none() && targets.is_none() {
+        return Vec::new();
+    }
+    let (head, tail) = chain.split_at_mut(plan.pivot);
+    let start = |ends: &[A]| ends.iter().map(|a| Path::new().push(a.clone())).collect();
+
+    let ahead = sources.map(|sources| {
+        head.iter_mut()
+            .fold(start(sources), |paths, step| expand(paths, step.forward()))
+    });
+    assert!(
+        ahead.is_some() || head.is_empty(),
+        "cannot evaluate forward from unbound sources"
+    );
+    let behind = targets.map(|targets| {
+        tail.iter_mut()
+            .rev()
+            .fold(start(targets), |paths, step| expand(paths, step.backward()))
+    });
+    assert!(
+        behind.is_some() || tail.is_empty(),
+        "cannot evaluate backward from unbound targets"
+    );
+
+    match (ahead, behind) {
+        (Some(ahead), Some(behind)) => join(&ahead, &behind),
+        (Some(ahead), None) => ahead.iter().map(Path::to_vec).collect(),
+        (None, Some(behind)) => behind
+            .iter()
+            .map(|p| p.iter_rev().cloned().collect())
+            .collect(),
+        (None, None) => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Step = BiArrow<fn(u32) -> Vec<u32>, fn(u32) -> Vec<u32>>;
+
+    // node n is connected to nodes 10n..10n+9 by `wide`, and to n+1 by
+    // `narrow`
+    fn wide() -> Step {
+        BiArrow::new(
+            (|n: u32| (10 * n..10 * n + 10).collect()) as fn(u32) -> Vec<u32>,
+            (|n: u32| vec![n / 10]) as fn(u32) -> Vec<u32>,
+        )
+        .with_fan_out(FanOut::new(10.0, 1.0))
+    }
+
+    fn narrow() -> Step {
+        BiArrow::new(
+            (|n: u32| vec![n + 1]) as fn(u32) -> Vec<u32>,
+            (|n: u32| n.checked_sub(1).into_iter().collect()) as fn(u32) -> Vec<u32>,
+        )
+    }
+
+    fn sorted(mut paths: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn plans_from_the_narrow_end() {
+        let chain = [wide(), wide(), wide()];
+        let both = ChainStats {
+            sources: Some(1.0),
+            targets: Some(1.0),
+            ..ChainStats::default()
+        };
+        assert_eq!(plan_chain(&chain, &both).unwrap().pivot(), 0);
+
+        let only_sources = ChainStats {
+            sources: Some(1.0),
+            ..ChainStats::default()
+        };
+        assert_eq!(plan_chain(&chain, &only_sources).unwrap().pivot(), 3);
+    }
+
+    #[test]
+    fn statistics_override_estimates() {
+        let chain = [narrow(), wide(), narrow()];
+        let stats = ChainStats {
+            sources: Some(1.0),
+            targets: Some(1.0),
+            fan_out: vec![
+                Some(FanOut::new(20.0, 20.0)),
+                None,
+                Some(FanOut::new(20.0, 20.0)),
+            ],
+        };
+        // both ends fan out, so meet next to the step that doesn't
+        assert_eq!(plan_chain(&chain, &stats).unwrap().pivot(), 1);
+
+        let estimates = ChainStats {
+            fan_out: Vec::new(),
+            ..stats
+        };
+        assert_eq!(plan_chain(&chain, &estimates).unwrap().pivot(), 0);
+    }
+
+    #[test]
+    fn every_pivot_finds_the_same_paths() {
+        let mut chain = [narrow(), wide(), narrow()];
+        let expected = vec![vec![2, 3, 31, 32], vec![2, 3, 33, 34]];
+        for pivot in 0..=chain.len() {
+            let plan = ChainPlan { pivot, cost: 0.0 };
+            let found = evaluate_chain(&mut chain, &plan, Some(&[2, 5]), Some(&[32, 34, 99]));
+            assert_eq!(sorted(found), expected, "pivot {pivot}");
+        }
+    }
+
+    #[test]
+    fn unbound_ends() {
+        let mut chain = [wide(), narrow()];
+        let stats = ChainStats {
+            targets: Some(1.0),
+            ..ChainStats::default()
+        };
+        let plan = plan_chain(&chain, &stats).unwrap();
+        assert_eq!(
+            evaluate_chain(&mut chain, &plan, None, Some(&[35])),
+            vec![vec![3, 34, 35]]
+        );
+
+        let plan = ChainPlan {
+            pivot: 2,
+            cost: 0.0,
+        };
+        assert_eq!(
+            evaluate_chain(&mut chain, &plan, Some(&[0]), None).len(),
+            10
+        );
+        assert!(plan_chain(&chain, &ChainStats::default()).is_none());
+        let plan = ChainPlan {
+            pivot: 1,
+            cost: 0.0,
+        };
+        assert!(evaluate_chain::<u32, _, _>(&mut chain, &plan, None, None).is_empty());
+        let plan = ChainPlan {
+            pivot: 0,
+            cost: 0.0,
+        };
+        assert!(evaluate_chain::<u32, _, _>(&mut [] as &mut [Step], &plan, None, None).is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+//! Set semantics for Kleisli arrows.
+//!
+//! Composed arrows have bag semantics: a result reachable along two
+//! routes is yielded twice. Wrapping an arrow with `kleisli_distinct`
+//! suppresses repeats within each application, remembering what has
+//! been seen in a `SeenSet` of the caller's choosing.
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{kleisli_compose, Kleisli, KleisliCompose};
+
+/// Records the values seen so far by a distinct arrow.
+pub trait SeenSet<T> {
+    /// Marks `t` as seen, returning whether it was new.
+    fn first_sight(&mut self, t: &T) -> bool;
+}
+
+impl<T: Hash + Eq + Clone, S: BuildHasher> SeenSet<T> for HashSet<T, S> {
+    fn first_sight(&mut self, t: &T) -> bool {
+        !self.contains(t) && self.insert(t.clone())
+    }
+}
+
+impl<T: Ord + Clone> SeenSet<T> for BTreeSet<T> {
+    fn first_sight(&mut self, t: &T) -> bool {
+        !self.contains(t) && self.insert(t.clone())
+    }
+}
+
+/// A growable bitset over dense `usize` ids, such as node numbers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        BitSet::default()
+    }
+
+    /// A bitset with room for ids below `n` without reallocating.
+    pub fn with_capacity(n: usize) -> Self {
+        BitSet {
+            words: Vec::with_capacity(n.div_ceil(64)),
+        }
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words
+            .get(i / 64)
+            .is_some_and(|w| w & (1 << (i % 64)) != 0)
+    }
+
+    /// Adds `i`, returning whether it was absent.
+    pub fn insert(&mut self, i: usize) -> bool {
+        let (word, bit) = (i / 64, 1 << (i % 64));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let absent = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        absent
+    }
+}
+
+impl SeenSet<usize> for BitSet {
+    fn first_sight(&mut self, t: &usize) -> bool {
+        self.insert(*t)
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliDistinct<F, S> {
+    f: F,
+    seen: S,
+}
+
+/// Yields each distinct result of `f` once, in order of first appearance.
+pub fn kleisli_distinct<A, F>(f: F) -> KleisliDistinct<F, HashSet<F::Output>>
+where
+    F: Kleisli<A>,
+    F::Output: Hash + Eq + Clone,
+{
+    KleisliDistinct {
+        f,
+        seen: HashSet::new(),
+    }
+}
+
+/// As `kleisli_distinct`, remembering results in a copy of `seen` for
+/// every application.
+pub fn kleisli_distinct_in<A, F, S>(f: F, seen: S) -> KleisliDistinct<F, S>
+where
+    F: Kleisli<A>,
+    S: SeenSet<F::Output>,
+{
+    KleisliDistinct { f, seen }
+}
+
+/// Composition `f >=> g` with set semantics.
+pub fn kleisli_compose_distinct<A, F, G>(
+    f: F,
+    g: G,
+) -> KleisliDistinct<KleisliCompose<F, G>, HashSet<G::Output>>
+where
+    F: Kleisli<A>,
+    G: Kleisli<F::Output> + Clone,
+    G::Output: Hash + Eq + Clone,
+{
+    kleisli_distinct(kleisli_compose(f, g))
+}
+
+impl<A, F, S> Kleisli<A> for KleisliDistinct<F, S>
+where
+    F: Kleisli<A>,
+    S: SeenSet<F::Output> + Clone,
+{
+    type Output = F::Output;
+    type Iter = ApplyKleisliDistinct<A, F, S>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliDistinct {
+            iter: self.f.apply(a),
+            seen: self.seen.clone(),
+        }
+    }
+}
+
+pub struct ApplyKleisliDistinct<A, F: Kleisli<A>, S> {
+    iter: F::Iter,
+    seen: S,
+}
+
+impl<A, F, S> Clone for ApplyKleisliDistinct<A, F, S>
+where
+    F: Kleisli<A>,
+    F::Iter: Clone,
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliDistinct {
+            iter: self.iter.clone(),
+            seen: self.seen.clone(),
+        }
+    }
+}
+
+impl<A, F: Kleisli<A>, S: SeenSet<F::Output>> ApplyKleisliDistinct<A, F, S> {
+    pub fn new(a: A, distinct: KleisliDistinct<F, S>) -> Self {
+        let KleisliDistinct { mut f, seen } = distinct;
+        ApplyKleisliDistinct {
+            iter: f.apply(a),
+            seen,
+        }
+    }
+}
+
+impl<A, F: Kleisli<A>, S: SeenSet<F::Output>> Iterator for ApplyKleisliDistinct<A, F, S> {
+    type Item = F::Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let seen = &mut self.seen;
+        self.iter.find(|x| seen.first_sight(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every node links to every node above it, so there are many
+    // routes to each.
+    fn dense(x: usize) -> Vec<usize> {
+        (x + 1..6).collect()
+    }
+
+    #[test]
+    fn compose_distinct_drops_repeats() {
+        let mut bag = kleisli_compose(dense, dense);
+        assert_eq!(
+            bag.apply(0).collect::<Vec<_>>(),
+            vec![2, 3, 4, 5, 3, 4, 5, 4, 5, 5]
+        );
+        let mut set = kleisli_compose_distinct(dense, dense);
+        assert_eq!(set.apply(0).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+        assert_eq!(set.apply(2).collect::<Vec<_>>(), vec![4, 5]);
+    }
+
+    #[test]
+    fn pluggable_seen_sets() {
+        let expected = vec![2, 3, 4, 5];
+        let mut btree = kleisli_distinct_in(kleisli_compose(dense, dense), BTreeSet::new());
+        assert_eq!(btree.apply(0).collect::<Vec<_>>(), expected);
+        let mut bits = kleisli_distinct_in(kleisli_compose(dense, dense), BitSet::with_capacity(6));
+        assert_eq!(bits.apply(0).collect::<Vec<_>>(), expected);
+        assert_eq!(bits.apply(1).collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn bitset_grows() {
+        let mut bits = BitSet::new();
+        assert!(bits.insert(3));
+        assert!(bits.insert(200));
+        assert!(!bits.insert(200));
+        assert!(bits.contains(3) && bits.contains(200));
+        assert!(!bits.contains(64) && !bits.contains(1000));
+    }
+}
@@ -0,0 +1,279 @@
+//! Kleisli composition of fallible arrows.
+//!
+//! A fallible arrow `A -> Result<[Result<B, E>], E>` may fail outright
+//! when applied, or on any individual result. Composing two of them
+//! converts the errors of the first stage into those of the second
+//! with `From`, and an `ErrorPolicy` decides whether the composed
+//! stream stops at the first error or carries on past it.
+use crate::Kleisli;
+
+/// A fallible Kleisli arrow. Closures returning
+/// `Result<impl IntoIterator<Item = Result<B, E>>, E>` are fallible
+/// arrows, as are the compositions built by `try_kleisli_compose`.
+pub trait TryKleisli<A> {
+    type Output;
+    type Error;
+    type Iter: Iterator<Item = Result<Self::Output, Self::Error>>;
+
+    fn try_apply(&mut self, a: A) -> Result<Self::Iter, Self::Error>;
+}
+
+impl<A, U, B, E, F> TryKleisli<A> for F
+where
+    F: FnMut(A) -> Result<U, E>,
+    U: IntoIterator<Item = Result<B, E>>,
+{
+    type Output = B;
+    type Error = E;
+    type Iter = U::IntoIter;
+
+    fn try_apply(&mut self, a: A) -> Result<Self::Iter, E> {
+        self(a).map(IntoIterator::into_iter)
+    }
+}
+
+/// What a composed stream does when it meets an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Yield the first error and then end the stream.
+    ShortCircuit,
+    /// Yield every error in place and keep going.
+    Collect,
+}
+
+#[derive(Clone)]
+pub struct TryKleisliCompose<F, G> {
+    f: F,
+    g: G,
+    policy: ErrorPolicy,
+}
+
+// Composition of fallible Kleisli arrows
+pub fn try_kleisli_compose<A, F, G>(f: F, g: G, policy: ErrorPolicy) -> TryKleisliCompose<F, G>
+where
+    F: TryKleisli<A>,
+    G: TryKleisli<F::Output>,
+    G::Error: From<F::Error>,
+{
+    TryKleisliCompose { f, g, policy }
+}
+
+/// Applying fails only if `f` fails outright; errors met along the
+/// way are reported in the stream according to the policy.
+impl<A, F, G> TryKleisli<A> for TryKleisliCompose<F, G>
+where
+    F: TryKleisli<A>,
+    G: TryKleisli<F::Output> + Clone,
+    G::Error: From<F::Error>,
+{
+    type Output = G::Output;
+    type Error = G::Error;
+    type Iter = ApplyTryKleisliCompose<A, F, G>;
+
+    fn try_apply(&mut self, a: A) -> Result<Self::Iter, Self::Error> {
+        Ok(ApplyTryKleisliCompose {
+            failed: None,
+            outer: Some(self.f.try_apply(a)?),
+            inner: None,
+            g: self.g.clone(),
+            policy: self.policy,
+        })
+    }
+}
+
+/// As an infallible arrow, a composition yields its errors as items,
+/// including the failure of `f` itself.
+impl<A, F, G> Kleisli<A> for TryKleisliCompose<F, G>
+where
+    F: TryKleisli<A>,
+    G: TryKleisli<F::Output> + Clone,
+    G::Error: From<F::Error>,
+{
+    type Output = Result<G::Output, G::Error>;
+    type Iter = ApplyTryKleisliCompose<A, F, G>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        let (failed, outer) = match self.f.try_apply(a) {
+            Ok(outer) => (None, Some(outer)),
+            Err(e) => (Some(e.into()), None),
+        };
+        ApplyTryKleisliCompose {
+            failed,
+            outer,
+            inner: None,
+            g: self.g.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+pub struct ApplyTryKleisliCompose<A, F: TryKleisli<A>, G: TryKleisli<F::Output>> {
+    failed: Option<G::Error>,
+    outer: Option<F::Iter>,
+    inner: Option<G::Iter>,
+    g: G,
+    policy: ErrorPolicy,
+}
+
+impl<A, F, G> Clone for ApplyTryKleisliCompose<A, F, G>
+where
+    F: TryKleisli<A>,
+    G: TryKleisli<F::Output> + Clone,
+    G::Error: Clone,
+    F::Iter: Clone,
+    G::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyTryKleisliCompose {
+            failed: self.failed.clone(),
+            outer: self.outer.clone(),
+            inner: self.inner.clone(),
+            g: self.g.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<A, F, G> ApplyTryKleisliCompose<A, F, G>
+where
+    F: TryKleisli<A>,
+    G: TryKleisli<F::Output>,
+    G::Error: From<F::Error>,
+{
+    fn fail(&mut self, e: G::Error) -> Option<Result<G::Output, G::Error>> {
+        if self.policy == ErrorPolicy::ShortCircuit {
+            self.outer = None;
+            self.inner = None;
+        }
+        Some(Err(e))
+    }
+}
+
+impl<A, F, G> Iterator for ApplyTryKleisliCompose<A, F, G>
+where
+    F: TryKleisli<A>,
+    G: TryKleisli<F::Output>,
+    G::Error: From<F::Error>,
+{
+    type Item = Result<G::Output, G::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.failed.take() {
+            return Some(Err(e));
+        }
+        loop {
+            if let Some(inner) = &mut self.inner {
+                match inner.next() {
+                    Some(Ok(c)) => return Some(Ok(c)),
+                    Some(Err(e)) => return self.fail(e),
+                    None => self.inner = None,
+                }
+            }
+            match self.outer.as_mut()?.next() {
+                Some(Ok(b)) => match self.g.try_apply(b) {
+                    Ok(inner) => self.inner = Some(inner),
+                    Err(e) => return self.fail(e),
+                },
+                Some(Err(e)) => return self.fail(e.into()),
+                None => {
+                    self.outer = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum StorageError {
+        Missing(u32),
+        Corrupt(u32),
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum QueryError {
+        Storage(StorageError),
+        Decode(String),
+    }
+
+    impl From<StorageError> for QueryError {
+        fn from(e: StorageError) -> Self {
+            QueryError::Storage(e)
+        }
+    }
+
+    fn fetch(x: u32) -> Result<Vec<Result<u32, StorageError>>, StorageError> {
+        match x {
+            0 => Err(StorageError::Missing(0)),
+            _ => Ok(vec![Ok(x), Err(StorageError::Corrupt(x)), Ok(x + 1)]),
+        }
+    }
+
+    fn decode(x: u32) -> Result<Vec<Result<String, QueryError>>, QueryError> {
+        if x.is_multiple_of(3) {
+            Err(QueryError::Decode(format!("{x}")))
+        } else {
+            Ok(vec![Ok(format!("n{x}"))])
+        }
+    }
+
+    #[test]
+    fn short_circuit_stops_at_first_error() {
+        let mut k = try_kleisli_compose(fetch, decode, ErrorPolicy::ShortCircuit);
+        let res: Vec<_> = k.try_apply(1).unwrap().collect();
+        assert_eq!(
+            res,
+            vec![
+                Ok("n1".to_string()),
+                Err(QueryError::Storage(StorageError::Corrupt(1)))
+            ]
+        );
+    }
+
+    #[test]
+    fn collect_reports_every_error_in_place() {
+        let mut k = try_kleisli_compose(fetch, decode, ErrorPolicy::Collect);
+        let res: Vec<_> = k.try_apply(2).unwrap().collect();
+        assert_eq!(
+            res,
+            vec![
+                Ok("n2".to_string()),
+                Err(QueryError::Storage(StorageError::Corrupt(2))),
+                Err(QueryError::Decode("3".to_string())),
+            ]
+        );
+        let ok: Result<Vec<_>, _> = k.try_apply(4).unwrap().collect();
+        assert_eq!(ok, Err(QueryError::Storage(StorageError::Corrupt(4))));
+    }
+
+    #[test]
+    fn failing_first_stage() {
+        let mut k = try_kleisli_compose(fetch, decode, ErrorPolicy::Collect);
+        assert_eq!(
+            k.try_apply(0).err(),
+            Some(QueryError::Storage(StorageError::Missing(0)))
+        );
+        assert_eq!(
+            k.apply(0).collect::<Vec<_>>(),
+            vec![Err(QueryError::Storage(StorageError::Missing(0)))]
+        );
+    }
+
+    #[test]
+    fn compositions_chain() {
+        let inner = try_kleisli_compose(fetch, fetch, ErrorPolicy::Collect);
+        let mut k = try_kleisli_compose(inner, decode, ErrorPolicy::ShortCircuit);
+        let res: Vec<_> = k.try_apply(1).unwrap().collect();
+        assert_eq!(
+            res,
+            vec![
+                Ok("n1".to_string()),
+                Err(QueryError::Storage(StorageError::Corrupt(1)))
+            ]
+        );
+    }
+}
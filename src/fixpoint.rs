@@ -0,0 +1,475 @@
+//! Fixed point combinators over Kleisli arrows `A -> [A]`.
+//!
+//! The closures enumerate the transitive closure of an arrow lazily
+//! and depth first, keeping a visited set so that cyclic graphs
+//! terminate; `kleisli_bfs` does the same breadth first, reaching near
+//! nodes before far ones. Bounded repetition instead follows every
+//! route, like nested `kleisli_compose`, without materialising
+//! intermediate results, while unbounded repetition follows every route
+//! up to its lower bound and continues with a closure.
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+use crate::{Kleisli, Path};
+
+#[derive(Clone)]
+pub struct KleisliStar<F> {
+    f: F,
+    reflexive: bool,
+}
+
+/// The reflexive transitive closure `f*`: the input itself followed
+/// by everything reachable from it in one or more steps.
+pub fn kleisli_star<A, F>(f: F) -> KleisliStar<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    KleisliStar { f, reflexive: true }
+}
+
+/// The transitive closure `f+`: everything reachable from the input
+/// in one or more steps. The input only appears if it lies on a cycle.
+pub fn kleisli_plus<A, F>(f: F) -> KleisliStar<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    KleisliStar {
+        f,
+        reflexive: false,
+    }
+}
+
+impl<A, F> Kleisli<A> for KleisliStar<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A> + Clone,
+{
+    type Output = A;
+    type Iter = ApplyKleisliStar<A, F>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliStar::new(a, self.clone())
+    }
+}
+
+pub struct ApplyKleisliStar<A, F: Kleisli<A>> {
+    f: F,
+    visited: HashSet<A>,
+    stack: Vec<F::Iter>,
+    start: Option<A>,
+}
+
+impl<A, F> Clone for ApplyKleisliStar<A, F>
+where
+    A: Clone,
+    F: Kleisli<A> + Clone,
+    F::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliStar {
+            f: self.f.clone(),
+            visited: self.visited.clone(),
+            stack: self.stack.clone(),
+            start: self.start.clone(),
+        }
+    }
+}
+
+impl<A, F> ApplyKleisliStar<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    pub fn new(a: A, star: KleisliStar<F>) -> Self {
+        let KleisliStar { mut f, reflexive } = star;
+        let mut visited = HashSet::new();
+        let start = if reflexive {
+            visited.insert(a.clone());
+            Some(a.clone())
+        } else {
+            None
+        };
+        let stack = vec![f.apply(a)];
+        ApplyKleisliStar {
+            f,
+            visited,
+            stack,
+            start,
+        }
+    }
+}
+
+impl<A, F> Iterator for ApplyKleisliStar<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    type Item = A;
+
+    fn next(&mut self) -> Option<A> {
+        if let Some(a) = self.start.take() {
+            return Some(a);
+        }
+        while let Some(top) = self.stack.last_mut() {
+            match top.next() {
+                Some(b) => {
+                    if self.visited.insert(b.clone()) {
+                        self.stack.push(self.f.apply(b.clone()));
+                        return Some(b);
+                    }
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliRepeat<F> {
+    f: F,
+    min: usize,
+    max: Option<usize>,
+}
+
+/// Bounded repetition `f{min,max}`: every result of composing `f` with
+/// itself between `min` and `max` times inclusive, along every route.
+///
+/// With `max` of `None` the upper bound is open, and the results are
+/// the nodes reachable in `min` or more steps, each yielded once as by
+/// `kleisli_star`, so the repetition terminates on cyclic arrows.
+pub fn kleisli_repeat<A, F>(f: F, min: usize, max: Option<usize>) -> KleisliRepeat<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    KleisliRepeat { f, min, max }
+}
+
+impl<A, F> Kleisli<A> for KleisliRepeat<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A> + Clone,
+{
+    type Output = A;
+    type Iter = ApplyKleisliRepeat<A, F>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliRepeat::new(a, self.clone())
+    }
+}
+
+pub struct ApplyKleisliRepeat<A, F: Kleisli<A>> {
+    f: F,
+    min: usize,
+    max: Option<usize>,
+    stack: Vec<F::Iter>,
+    // Nodes reached in `min` or more steps, when `max` is open
+    visited: HashSet<A>,
+    start: Option<A>,
+}
+
+impl<A, F> Clone for ApplyKleisliRepeat<A, F>
+where
+    A: Clone,
+    F: Kleisli<A> + Clone,
+    F::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliRepeat {
+            f: self.f.clone(),
+            min: self.min,
+            max: self.max,
+            stack: self.stack.clone(),
+            visited: self.visited.clone(),
+            start: self.start.clone(),
+        }
+    }
+}
+
+impl<A, F> ApplyKleisliRepeat<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    pub fn new(a: A, repeat: KleisliRepeat<F>) -> Self {
+        let KleisliRepeat { mut f, min, max } = repeat;
+        let mut stack = Vec::new();
+        let mut visited = HashSet::new();
+        let start = if min == 0 {
+            if max.is_none() {
+                visited.insert(a.clone());
+            }
+            Some(a.clone())
+        } else {
+            None
+        };
+        if max.is_none_or(|max| max > 0 && min <= max) {
+            stack.push(f.apply(a));
+        }
+        ApplyKleisliRepeat {
+            f,
+            min,
+            max,
+            stack,
+            visited,
+            start,
+        }
+    }
+}
+
+impl<A, F> Iterator for ApplyKleisliRepeat<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    type Item = A;
+
+    fn next(&mut self) -> Option<A> {
+        if let Some(a) = self.start.take() {
+            return Some(a);
+        }
+        while let Some(top) = self.stack.last_mut() {
+            let b = match top.next() {
+                Some(b) => b,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let depth = self.stack.len();
+            if self.max.is_none() && depth >= self.min {
+                // past the lower bound, expand each node once
+                if self.visited.insert(b.clone()) {
+                    self.stack.push(self.f.apply(b.clone()));
+                    return Some(b);
+                }
+                continue;
+            }
+            let expand = self.max.is_none_or(|max| depth < max);
+            if depth >= self.min {
+                if expand {
+                    self.stack.push(self.f.apply(b.clone()));
+                }
+                return Some(b);
+            } else if expand {
+                self.stack.push(self.f.apply(b));
+            }
+        }
+        None
+    }
+}
+
+/// Breadth first enumeration of the reflexive transitive closure of
+/// `f` from `start`, each node annotated with its distance from `start`.
+pub fn kleisli_bfs<A, F>(f: F, start: A) -> KleisliBfs<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    let mut visited = HashSet::new();
+    visited.insert(start.clone());
+    KleisliBfs {
+        f,
+        visited,
+        queue: VecDeque::from([Path::new().push(start)]),
+        expand: None,
+    }
+}
+
+/// The shortest path from `start` to the first node reached that
+/// satisfies `target`, both ends included. The search stops as soon
+/// as such a node is found.
+pub fn shortest_path<A, F, P>(f: F, start: A, mut target: P) -> Option<Vec<A>>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+    P: FnMut(&A) -> bool,
+{
+    let mut bfs = kleisli_bfs(f, start);
+    while let Some(path) = bfs.next_path() {
+        if path.last().is_some_and(&mut target) {
+            return Some(path.to_vec());
+        }
+    }
+    None
+}
+
+pub struct KleisliBfs<A, F> {
+    f: F,
+    visited: HashSet<A>,
+    queue: VecDeque<Path<A>>,
+    expand: Option<Path<A>>,
+}
+
+impl<A, F> Clone for KleisliBfs<A, F>
+where
+    A: Clone,
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        KleisliBfs {
+            f: self.f.clone(),
+            visited: self.visited.clone(),
+            queue: self.queue.clone(),
+            expand: self.expand.clone(),
+        }
+    }
+}
+
+impl<A, F> KleisliBfs<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    /// The next node, as the path from `start` by which it was first
+    /// reached. Its successors are only looked up once the following
+    /// node is asked for.
+    pub fn next_path(&mut self) -> Option<Path<A>> {
+        if let Some(path) = self.expand.take() {
+            if let Some(a) = path.last() {
+                for b in self.f.apply(a.clone()) {
+                    if self.visited.insert(b.clone()) {
+                        self.queue.push_back(path.push(b));
+                    }
+                }
+            }
+        }
+        let path = self.queue.pop_front()?;
+        self.expand = Some(path.clone());
+        Some(path)
+    }
+}
+
+impl<A, F> Iterator for KleisliBfs<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    type Item = (A, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let path = self.next_path()?;
+        let a = path.last()?.clone();
+        Some((a, path.len() - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kleisli_compose;
+
+    fn edges(db: &'static [(usize, usize)]) -> impl Fn(usize) -> Vec<usize> + Clone {
+        move |x| db.iter().filter(|e| e.0 == x).map(|e| e.1).collect()
+    }
+
+    const CYCLE: &[(usize, usize)] = &[(1, 2), (2, 3), (3, 1), (3, 4), (5, 6)];
+
+    #[test]
+    fn star_includes_start_and_terminates_on_cycles() {
+        let mut star = kleisli_star(edges(CYCLE));
+        assert_eq!(star.apply(1).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(star.apply(4).collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn plus_only_reaches_start_through_a_cycle() {
+        let mut plus = kleisli_plus(edges(CYCLE));
+        assert_eq!(plus.apply(1).collect::<Vec<_>>(), vec![2, 3, 1, 4]);
+        assert_eq!(plus.apply(5).collect::<Vec<_>>(), vec![6]);
+        assert_eq!(plus.apply(6).count(), 0);
+    }
+
+    const CHAIN: &[(usize, usize)] = &[(1, 2), (1, 3), (2, 4), (3, 4), (4, 5)];
+
+    #[test]
+    fn repeat_between_bounds() {
+        let mut two_to_three = kleisli_repeat(edges(CHAIN), 2, Some(3));
+        assert_eq!(two_to_three.apply(1).collect::<Vec<_>>(), vec![4, 5, 4, 5]);
+
+        let mut opt = kleisli_repeat(edges(CHAIN), 0, Some(1));
+        assert_eq!(opt.apply(4).collect::<Vec<_>>(), vec![4, 5]);
+
+        let mut none = kleisli_repeat(edges(CHAIN), 0, Some(0));
+        assert_eq!(none.apply(4).collect::<Vec<_>>(), vec![4]);
+
+        let mut empty = kleisli_repeat(edges(CHAIN), 3, Some(2));
+        assert_eq!(empty.apply(1).count(), 0);
+    }
+
+    #[test]
+    fn repeat_exactly_matches_nested_compose() {
+        let mut nested = kleisli_compose(edges(CHAIN), kleisli_compose(edges(CHAIN), edges(CHAIN)));
+        let mut exactly_three = kleisli_repeat(edges(CHAIN), 3, Some(3));
+        assert_eq!(
+            exactly_three.apply(1).collect::<Vec<_>>(),
+            nested.apply(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn repeat_unbounded_terminates_on_cycles() {
+        let mut at_least_two = kleisli_repeat(edges(CHAIN), 2, None);
+        assert_eq!(at_least_two.apply(1).collect::<Vec<_>>(), vec![4, 5]);
+
+        let mut around = kleisli_repeat(edges(CYCLE), 2, None);
+        assert_eq!(around.apply(1).collect::<Vec<_>>(), vec![3, 1, 2, 4]);
+
+        let mut star = kleisli_repeat(edges(CYCLE), 0, None);
+        assert_eq!(
+            star.apply(1).collect::<Vec<_>>(),
+            kleisli_star(edges(CYCLE)).apply(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn forked_closure_keeps_its_visited_set() {
+        let mut it = kleisli_star(edges(CYCLE)).apply(1);
+        assert_eq!(it.next(), Some(1));
+        assert_eq!(it.next(), Some(2));
+        let fork = it.clone();
+        assert_eq!(it.collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(fork.collect::<Vec<_>>(), vec![3, 4]);
+
+        let mut it = kleisli_repeat(edges(CHAIN), 1, None).apply(1);
+        assert_eq!(it.next(), Some(2));
+        let fork = it.clone();
+        assert_eq!(it.collect::<Vec<_>>(), fork.collect::<Vec<_>>());
+    }
+
+    const LADDER: &[(usize, usize)] = &[(1, 2), (2, 3), (3, 4), (4, 5), (1, 6), (6, 5), (5, 1)];
+
+    #[test]
+    fn bfs_reaches_near_nodes_first() {
+        let order: Vec<_> = kleisli_bfs(edges(LADDER), 1).collect();
+        assert_eq!(order, vec![(1, 0), (2, 1), (6, 1), (3, 2), (5, 2), (4, 3)]);
+        let depth_first: Vec<_> = kleisli_star(edges(LADDER)).apply(1).collect();
+        assert_eq!(depth_first, vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn shortest_path_stops_at_target() {
+        let looked_up = std::cell::RefCell::new(Vec::new());
+        let lookup = |x: usize| {
+            looked_up.borrow_mut().push(x);
+            edges(LADDER)(x)
+        };
+        assert_eq!(shortest_path(lookup, 1, |x| *x == 5), Some(vec![1, 6, 5]));
+        assert_eq!(*looked_up.borrow(), vec![1, 2, 6, 3]);
+
+        assert_eq!(shortest_path(edges(LADDER), 4, |x| *x == 4), Some(vec![4]));
+        assert_eq!(shortest_path(edges(CHAIN), 5, |x| *x == 1), None);
+    }
+
+    #[test]
+    fn closures_compose() {
+        let mut k = kleisli_compose(kleisli_plus(edges(CYCLE)), edges(CYCLE));
+        assert_eq!(k.apply(5).count(), 0);
+        assert_eq!(k.apply(3).collect::<Vec<_>>(), vec![2, 3, 1, 4]);
+    }
+}
@@ -4,137 +4,566 @@
 //! compile branching and sequencing operations easily, as well as
 //! *fixed point* operations, which are need for path queries.
 //!
-//! The Kleisli composiiton takes a generator of an iterator:
+//! The Kleisli composition takes generators of iterators and chains
+//! them, so that every result of the first is fed to the second:
 //! ```rust
-//! let f: Fn(A) -> Iterator<Item=B>
-//! let g: Fn(B) -> Iterator<Item=C>
-//! let f_g = kleislicompose(f,g)
-//! let x : Vec<C> = f_g.collect()
+//! use kleisli::{kleisli_compose, ApplyKleisliCompose};
+//!
+//! let f = |x: usize| vec![x, x + 1];
+//! let g = |y: usize| vec![y * 10];
+//! let f_g = kleisli_compose(f, g);
+//! let x: Vec<usize> = ApplyKleisliCompose::new(1, f_g).collect();
+//! assert_eq!(x, vec![10, 20]);
 //! ```
 //!
-//! The iterators should also be clonable, which is important for
-//! producing back tracking combinators.
+//! The iterators are also clonable whenever the arrows and the
+//! intermediate iterators are, which is important for producing back
+//! tracking combinators: a partially consumed stream can be forked and
+//! both copies resumed from the same point.
 //!
 use std::iter::IntoIterator;
 
-pub struct KleisliCompose<
-    A: Copy,
-    U: IntoIterator,
-    S: IntoIterator,
-    F: FnMut(A) -> U,
-    G: FnMut(U::Item) -> S,
-> {
-    _a: std::marker::PhantomData<A>,
+mod automaton;
+mod bidi;
+mod boxed;
+mod choice;
+mod cost;
+mod distinct;
+mod fallible;
+mod fixpoint;
+mod memo;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod parse;
+mod path;
+mod plan;
+#[cfg(feature = "async")]
+mod stream;
+mod trace;
+mod weighted;
+
+pub use automaton::{compile_path_automaton, ApplyPathAutomaton, AutomatonTooLarge, PathAutomaton};
+pub use bidi::{bi_kleisli_compose, meet_in_middle, BiArrow};
+pub use boxed::BoxKleisli;
+pub use choice::{
+    kleisli_choice, kleisli_or, ApplyKleisliChoice, ApplyKleisliOr, KleisliChoice, KleisliOr,
+};
+pub use cost::{evaluate_chain, plan_chain, ChainPlan, ChainStats, FanOut};
+pub use distinct::{
+    kleisli_compose_distinct, kleisli_distinct, kleisli_distinct_in, ApplyKleisliDistinct, BitSet,
+    KleisliDistinct, SeenSet,
+};
+pub use fallible::{
+    try_kleisli_compose, ApplyTryKleisliCompose, ErrorPolicy, TryKleisli, TryKleisliCompose,
+};
+pub use fixpoint::{
+    kleisli_bfs, kleisli_plus, kleisli_repeat, kleisli_star, shortest_path, ApplyKleisliRepeat,
+    ApplyKleisliStar, KleisliBfs, KleisliRepeat, KleisliStar,
+};
+pub use memo::{memoize, memoize_lru, ApplyMemoize, Memoize};
+#[cfg(feature = "rayon")]
+pub use parallel::ParOrder;
+pub use parse::{parse_path, ParseError, ParseErrorKind};
+pub use path::{compile_path, compile_path_bi, Direction, PathExpr};
+pub use plan::{
+    kleisli_lookup, planned_choice, planned_compose, planned_distinct, planned_plus,
+    planned_repeat, planned_star, ApplyPlanned, Plan, PlanOp, Planned,
+};
+#[cfg(feature = "async")]
+pub use stream::{
+    async_kleisli_compose, async_kleisli_compose_unordered, ApplyAsyncKleisliCompose, AsyncKleisli,
+    AsyncKleisliCompose,
+};
+pub use trace::{
+    kleisli_compose_path, kleisli_plus_path, kleisli_star_path, kleisli_trace,
+    ApplyKleisliComposePath, ApplyKleisliStarPath, ApplyKleisliTrace, KleisliComposePath,
+    KleisliStarPath, KleisliTrace, Path,
+};
+pub use weighted::{
+    kleisli_aggregate, kleisli_astar, kleisli_dijkstra, weighted_kleisli_compose,
+    ApplyWeightedKleisliCompose, Boolean, Counting, KleisliAggregate, KleisliDijkstra, Probability,
+    Semiring, Tropical, WeightedKleisliCompose,
+};
+
+/// A Kleisli arrow `A -> [Output]`.
+///
+/// Closures returning anything `IntoIterator` are arrows, as are the
+/// composed arrows built by this crate, so compositions can be
+/// composed again. Applying an arrow hands back an owned iterator
+/// which does not borrow from the arrow.
+///
+/// ```rust
+/// use kleisli::{kleisli_compose, Kleisli};
+///
+/// #[derive(Clone)]
+/// struct Edges(Vec<(usize, usize)>);
+///
+/// impl Kleisli<usize> for Edges {
+///     type Output = usize;
+///     type Iter = std::vec::IntoIter<usize>;
+///
+///     fn apply(&mut self, a: usize) -> Self::Iter {
+///         let out: Vec<_> = self.0.iter().filter(|e| e.0 == a).map(|e| e.1).collect();
+///         out.into_iter()
+///     }
+/// }
+///
+/// let edges = Edges(vec![(1, 2), (2, 3), (2, 4)]);
+/// let mut two_hops = kleisli_compose(edges.clone(), edges);
+/// assert_eq!(two_hops.apply(1).collect::<Vec<_>>(), vec![3, 4]);
+/// ```
+pub trait Kleisli<A> {
+    type Output;
+    type Iter: Iterator<Item = Self::Output>;
+
+    fn apply(&mut self, a: A) -> Self::Iter;
+}
+
+impl<A, U: IntoIterator, F: FnMut(A) -> U> Kleisli<A> for F {
+    type Output = U::Item;
+    type Iter = U::IntoIter;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        self(a).into_iter()
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliCompose<F, G> {
     f: F,
     g: G,
 }
 
-impl<A: Copy, U: IntoIterator, S: IntoIterator, F: FnMut(A) -> U, G: FnMut(U::Item) -> S>
-    KleisliCompose<A, U, S, F, G>
+impl<F, G> KleisliCompose<F, G> {
+    pub fn new(f: F, g: G) -> KleisliCompose<F, G> {
+        KleisliCompose { f, g }
+    }
+}
+
+// Composition of Kleisli arrows (>=>)
+pub fn kleisli_compose<A, F, G>(f: F, g: G) -> KleisliCompose<F, G>
+where
+    F: Kleisli<A>,
+    G: Kleisli<F::Output>,
 {
-    pub fn new(f: F, g: G) -> KleisliCompose<A, U, S, F, G> {
-        KleisliCompose {
-            _a: Default::default(),
-            f,
-            g,
+    KleisliCompose::new(f, g)
+}
+
+/// Applying a composed arrow clones `g`, as every application needs
+/// its own copy to expand the intermediate results with. The input
+/// is moved into `f`, so arrows over references (`apply(&a)`) and
+/// non-`Copy` values compose alike.
+impl<A, F, G> Kleisli<A> for KleisliCompose<F, G>
+where
+    F: Kleisli<A>,
+    G: Kleisli<F::Output> + Clone,
+{
+    type Output = G::Output;
+    type Iter = ApplyKleisliCompose<A, F, G>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliCompose {
+            outer: self.f.apply(a),
+            inner: None,
+            g: self.g.clone(),
         }
     }
 }
 
-// Composition of Kleisli arrows (>=>)x
-pub fn kleisli_compose<A, U, S, F, G>(f: F, g: G) -> KleisliCompose<A, U, S, F, G>
+pub struct ApplyKleisliCompose<A, F: Kleisli<A>, G: Kleisli<F::Output>> {
+    outer: F::Iter,
+    inner: Option<G::Iter>,
+    g: G,
+}
+
+impl<A, F, G> Clone for ApplyKleisliCompose<A, F, G>
 where
-    A: Copy,
-    U: IntoIterator,
-    S: IntoIterator,
-    F: FnMut(A) -> U,
-    G: FnMut(U::Item) -> S,
+    F: Kleisli<A>,
+    G: Kleisli<F::Output> + Clone,
+    F::Iter: Clone,
+    G::Iter: Clone,
 {
-    KleisliCompose::new(f, g)
+    fn clone(&self) -> Self {
+        ApplyKleisliCompose {
+            outer: self.outer.clone(),
+            inner: self.inner.clone(),
+            g: self.g.clone(),
+        }
+    }
 }
 
-pub struct ApplyKleisliCompose<
-    A: Copy,
-    U: IntoIterator,
-    S: IntoIterator,
-    F: FnMut(A) -> U,
-    G: FnMut(U::Item) -> S,
-> {
-    a: A,
-    k: KleisliCompose<A, U, S, F, G>,
+impl<A, F: Kleisli<A>, G: Kleisli<F::Output>> ApplyKleisliCompose<A, F, G> {
+    /// Applies `f` to `a` once, the results of which are expanded
+    /// with `g` lazily as the iterator is driven.
+    pub fn new(a: A, kc: KleisliCompose<F, G>) -> Self {
+        let KleisliCompose { mut f, g } = kc;
+        ApplyKleisliCompose {
+            outer: f.apply(a),
+            inner: None,
+            g,
+        }
+    }
 }
 
-impl<A: Copy, U: IntoIterator, S: IntoIterator, F: FnMut(A) -> U, G: FnMut(U::Item) -> S>
-    ApplyKleisliCompose<A, U, S, F, G>
+impl<A, F: Kleisli<A>, G: Kleisli<F::Output>> Iterator for ApplyKleisliCompose<A, F, G> {
+    type Item = G::Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(inner) = &mut self.inner {
+                if let Some(x) = inner.next() {
+                    return Some(x);
+                }
+            }
+            match self.outer.next() {
+                Some(b) => self.inner = Some(self.g.apply(b)),
+                None => {
+                    self.inner = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliComposeAll<F> {
+    arrows: Vec<F>,
+}
+
+/// N-ary composition `f >=> g >=> h >=> ...` over arrows `A -> [A]` of
+/// the same type. Results are expanded depth first from a stack of
+/// iterators rather than nested ones, so long chains compose without
+/// deep recursion. An empty composition is `ret`.
+pub fn kleisli_compose_all<A, F, I>(arrows: I) -> KleisliComposeAll<F>
+where
+    F: Kleisli<A, Output = A>,
+    I: IntoIterator<Item = F>,
 {
-    pub fn new(a: A, kc: KleisliCompose<A, U, S, F, G>) -> Self {
-        ApplyKleisliCompose { a, k: kc }
+    KleisliComposeAll {
+        arrows: arrows.into_iter().collect(),
     }
 }
 
-impl<A: Copy, U: IntoIterator, S: IntoIterator, F: FnMut(A) -> U, G: FnMut(U::Item) -> S> Iterator
-    for ApplyKleisliCompose<A, U, S, F, G>
+impl<A, F> Kleisli<A> for KleisliComposeAll<F>
+where
+    F: Kleisli<A, Output = A> + Clone,
 {
-    type Item = S::Item;
+    type Output = A;
+    type Iter = ApplyKleisliComposeAll<A, F>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        (self.k.f)(self.a)
-            .into_iter()
-            .flat_map(|x| (self.k.g)(x).into_iter())
-            .next()
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliComposeAll::new(a, self.clone())
+    }
+}
+
+pub struct ApplyKleisliComposeAll<A, F: Kleisli<A>> {
+    arrows: Vec<F>,
+    // `stack[i]` yields the results of the first `i + 1` arrows
+    stack: Vec<F::Iter>,
+    start: Option<A>,
+}
+
+impl<A, F> Clone for ApplyKleisliComposeAll<A, F>
+where
+    A: Clone,
+    F: Kleisli<A> + Clone,
+    F::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliComposeAll {
+            arrows: self.arrows.clone(),
+            stack: self.stack.clone(),
+            start: self.start.clone(),
+        }
     }
 }
 
-fn compile(y: usize) -> impl Fn(usize) -> Vec<usize> {
-    move |x| {
-        let mut vec = Vec::new();
-        for i in x..y {
-            vec.push(i);
+impl<A, F: Kleisli<A, Output = A>> ApplyKleisliComposeAll<A, F> {
+    pub fn new(a: A, kc: KleisliComposeAll<F>) -> Self {
+        let KleisliComposeAll { mut arrows } = kc;
+        let (stack, start) = match arrows.first_mut() {
+            Some(f) => (vec![f.apply(a)], None),
+            None => (Vec::new(), Some(a)),
+        };
+        ApplyKleisliComposeAll {
+            arrows,
+            stack,
+            start,
         }
-        vec
     }
 }
 
-use fp_rust::fp::foldr;
+impl<A, F: Kleisli<A, Output = A>> Iterator for ApplyKleisliComposeAll<A, F> {
+    type Item = A;
+
+    fn next(&mut self) -> Option<A> {
+        if let Some(a) = self.start.take() {
+            return Some(a);
+        }
+        while let Some(top) = self.stack.last_mut() {
+            let Some(b) = top.next() else {
+                self.stack.pop();
+                continue;
+            };
+            match self.arrows.get_mut(self.stack.len()) {
+                Some(f) => self.stack.push(f.apply(b)),
+                None => return Some(b),
+            }
+        }
+        None
+    }
+}
 
-fn ret(x: usize) -> Vec<usize> {
+/// The unit of the Kleisli category: yields its argument exactly once.
+pub fn ret<A>(x: A) -> Vec<A> {
     vec![x]
 }
 
-fn compose_repeat(v: Vec<usize>) -> impl Fn(usize) -> Vec<usize> {
-    foldr(|f, x| |y| f(x).iter().flatmap(, &ret, v)
+/// Composes a sequence of arrows `A -> [A]` into a single arrow,
+/// folding from `ret` so that an empty sequence is the identity.
+pub fn compose_repeat<A, F, I>(arrows: I) -> impl FnMut(A) -> Vec<A>
+where
+    F: Kleisli<A, Output = A>,
+    I: IntoIterator<Item = F>,
+{
+    let mut arrows: Vec<F> = arrows.into_iter().collect();
+    move |a| {
+        arrows.iter_mut().fold(ret(a), |xs, f| {
+            xs.into_iter().flat_map(|x| f.apply(x)).collect()
+        })
+    }
+}
+
+/// Variadic Kleisli composition of a static chain of arrows.
+///
+/// `kleisli![f, g, h]` is `f >=> g >=> h`, and `kleisli![]` is `ret`.
+#[macro_export]
+macro_rules! kleisli {
+    () => {
+        $crate::ret
+    };
+    ($f:expr $(,)?) => {
+        $f
+    };
+    ($f:expr, $($rest:expr),+ $(,)?) => {
+        $crate::kleisli_compose($f, $crate::kleisli![$($rest),+])
+    };
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn compile(y: usize) -> impl Fn(usize) -> Vec<usize> {
+        move |x| {
+            let mut vec = Vec::new();
+            for i in x..y {
+                vec.push(i);
+            }
+            vec
+        }
+    }
+
     #[test]
-    fn clone_it() {
-        todo!()
-    }
-    /*
-        #[test]
-        fn clone_it() {
-            let db = vec![(1, 2), (2, 3), (3, 4), (1, 5), (5, 7)];
-            let iter = db.into_iter();
-            let iter2 = iter.clone();
-            let iter3 = iter.clone();
-            let res: Vec<_> = iter
-                .flat_map(|i| {
-                    ApplyKleisliCompose::new(
-                        i,
-                        kleisli_compose(
-                            move |t: (usize, usize)| {
-                                iter3.clone().map(|x| x.1).filter(move |x| *x == t.0)
-                            },
-                            move |s| iter2.clone().map(|x| x.1).filter(move |x| *x == s),
-                        ),
-                    )
-                })
-                .collect();
-            eprintln!("{res:?}");
-            panic!()
+    fn apply_streams_every_result() {
+        let mut calls = 0;
+        let k = kleisli_compose(
+            |x: usize| {
+                calls += 1;
+                vec![x, x + 1, x + 2]
+            },
+            |y: usize| vec![y * 10, y * 100],
+        );
+        let res: Vec<_> = ApplyKleisliCompose::new(1, k).collect();
+        assert_eq!(res, vec![10, 100, 20, 200, 30, 300]);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn apply_skips_empty_expansions() {
+        let k = kleisli_compose(
+            |x: usize| 0..x,
+            |y: usize| if y.is_multiple_of(2) { vec![] } else { vec![y] },
+        );
+        let res: Vec<_> = ApplyKleisliCompose::new(6, k).collect();
+        assert_eq!(res, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn compose_repeat_folds_arrows() {
+        let mut h = compose_repeat(vec![compile(4), compile(4)]);
+        assert_eq!(h(2), vec![2, 3, 3]);
+        let mut id = compose_repeat(Vec::<fn(usize) -> Vec<usize>>::new());
+        assert_eq!(id(7), vec![7]);
+    }
+
+    #[test]
+    fn kleisli_macro_chains() {
+        let mut h = kleisli![compile(4), |x: usize| vec![x, x * 10], ret];
+        assert_eq!(h.apply(2).collect::<Vec<_>>(), vec![2, 20, 3, 30]);
+        let mut id = kleisli![];
+        assert_eq!(id.apply(5).collect::<Vec<usize>>(), vec![5]);
+    }
+
+    #[derive(Clone)]
+    struct Successors {
+        limit: usize,
+    }
+
+    impl Kleisli<usize> for Successors {
+        type Output = usize;
+        type Iter = std::ops::Range<usize>;
+
+        fn apply(&mut self, a: usize) -> Self::Iter {
+            a + 1..self.limit
         }
-    */
+    }
+
+    #[test]
+    fn composed_arrows_compose_again() {
+        let mut twice = kleisli_compose(Successors { limit: 4 }, Successors { limit: 4 });
+        assert_eq!(twice.apply(0).collect::<Vec<_>>(), vec![2, 3, 3]);
+        assert_eq!(twice.apply(1).collect::<Vec<_>>(), vec![3]);
+
+        let mut thrice = kleisli_compose(twice, |x: usize| vec![x * 10]);
+        assert_eq!(thrice.apply(0).collect::<Vec<_>>(), vec![20, 30, 30]);
+
+        let mut repeated = compose_repeat(vec![thrice.clone(), thrice]);
+        assert_eq!(repeated(0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn compose_all_matches_nested_compose() {
+        let next = Successors { limit: 5 };
+        let mut all = kleisli_compose_all(vec![next.clone(); 3]);
+        let mut nested = kleisli![next.clone(), next.clone(), next];
+        assert_eq!(
+            all.apply(0).collect::<Vec<_>>(),
+            nested.apply(0).collect::<Vec<_>>()
+        );
+        let mut id = kleisli_compose_all(Vec::<Successors>::new());
+        assert_eq!(id.apply(7).collect::<Vec<_>>(), vec![7]);
+
+        let mut long = kleisli_compose_all(vec![|x: usize| vec![x + 1]; 100_000]);
+        assert_eq!(long.apply(0).collect::<Vec<_>>(), vec![100_000]);
+    }
+
+    #[test]
+    fn non_copy_inputs() {
+        use std::sync::Arc;
+
+        let mut owned = kleisli_compose(
+            |iri: String| vec![format!("{iri}/a"), format!("{iri}/b")],
+            |iri: String| vec![Arc::<str>::from(iri)],
+        );
+        let res: Vec<_> = owned.apply("x".to_string()).collect();
+        assert_eq!(res, vec![Arc::from("x/a"), Arc::from("x/b")]);
+
+        let mut by_ref = kleisli_compose(
+            |node: &Arc<str>| vec![node.clone(), Arc::from(format!("{node}!"))],
+            |node: Arc<str>| vec![node.len()],
+        );
+        let node: Arc<str> = Arc::from("node");
+        assert_eq!(by_ref.apply(&node).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(by_ref.apply(&node).collect::<Vec<_>>(), vec![4, 5]);
+
+        let tuple = (vec![1, 2], vec![3]);
+        let res: Vec<_> = ApplyKleisliCompose::new(
+            tuple,
+            kleisli_compose(
+                |(l, r): (Vec<usize>, Vec<usize>)| vec![l, r],
+                |v: Vec<usize>| v,
+            ),
+        )
+        .collect();
+        assert_eq!(res, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn fork_partially_consumed_stream() {
+        let k = kleisli_compose(|x: usize| vec![x, x + 1], |y: usize| vec![y, y * 10]);
+        let mut it = ApplyKleisliCompose::new(1, k);
+        assert_eq!(it.next(), Some(1));
+        let fork = it.clone();
+        assert_eq!(it.collect::<Vec<_>>(), vec![10, 2, 20]);
+        assert_eq!(fork.collect::<Vec<_>>(), vec![10, 2, 20]);
+    }
+
+    const DB: &[(usize, usize)] = &[(1, 2), (2, 3), (3, 4), (1, 5), (5, 7)];
+
+    fn forward(db: &'static [(usize, usize)]) -> impl Fn(usize) -> Vec<usize> + Clone {
+        move |s| db.iter().filter(|e| e.0 == s).map(|e| e.1).collect()
+    }
+
+    fn backward(db: &'static [(usize, usize)]) -> impl Fn(usize) -> Vec<usize> + Clone {
+        move |t| db.iter().filter(|e| e.1 == t).map(|e| e.0).collect()
+    }
+
+    #[test]
+    fn clone_it() {
+        let iter = DB.iter().copied();
+        let iter2 = iter.clone();
+        let iter3 = iter.clone();
+        let mut res = iter.flat_map(|i| {
+            let iter2 = iter2.clone();
+            let iter3 = iter3.clone();
+            ApplyKleisliCompose::new(
+                i,
+                kleisli_compose(
+                    move |t: (usize, usize)| iter3.clone().filter(move |x| x.0 == t.1).map(|x| x.1),
+                    move |s: usize| iter2.clone().filter(move |x| x.0 == s).map(|x| x.1),
+                ),
+            )
+        });
+        let fork = res.clone();
+        assert_eq!(res.next(), Some(4));
+        assert_eq!(res.next(), None);
+        assert_eq!(fork.collect::<Vec<_>>(), vec![4]);
+    }
+
+    #[test]
+    fn two_hop_join() {
+        let mut two_hops = kleisli_compose(forward(DB), forward(DB));
+        assert_eq!(two_hops.apply(1).collect::<Vec<_>>(), vec![3, 7]);
+        assert_eq!(two_hops.apply(2).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(two_hops.apply(4).count(), 0);
+
+        let mut round_trip = kleisli_compose(forward(DB), backward(DB));
+        assert_eq!(round_trip.apply(2).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn closure_over_edges() {
+        let mut reach = kleisli_star(forward(DB));
+        assert_eq!(reach.apply(1).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 7]);
+
+        let mut sources = kleisli_plus(backward(DB));
+        assert_eq!(sources.apply(4).collect::<Vec<_>>(), vec![3, 2, 1]);
+
+        let mut chain = kleisli_compose(kleisli_plus(forward(DB)), backward(DB));
+        assert_eq!(chain.apply(2).collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn alternation_over_edges() {
+        let mut neighbours = kleisli_choice(forward(DB), backward(DB));
+        assert_eq!(neighbours.apply(2).collect::<Vec<_>>(), vec![3, 1]);
+
+        let mut undirected = kleisli_star(kleisli_choice(forward(DB), backward(DB)));
+        let mut component: Vec<_> = undirected.apply(7).collect();
+        component.sort();
+        assert_eq!(component, vec![1, 2, 3, 4, 5, 7]);
+    }
+
+    #[test]
+    fn forked_join_resumes_independently() {
+        let mut it = kleisli_compose(kleisli_star(forward(DB)), forward(DB)).apply(1);
+        assert_eq!(it.next(), Some(2));
+        assert_eq!(it.next(), Some(5));
+        let mut fork = it.clone();
+        assert_eq!(fork.next(), Some(3));
+        assert_eq!(it.collect::<Vec<_>>(), vec![3, 4, 7]);
+        assert_eq!(fork.collect::<Vec<_>>(), vec![4, 7]);
+    }
 }
@@ -0,0 +1,257 @@
+//! Memoized arrows.
+//!
+//! Fixed points and compositions apply an arrow to the same input many
+//! times over, once for every route reaching it. `memoize` caches the
+//! results of each application so that an expensive lookup only runs
+//! once per input. The cache is shared between clones of the arrow, so it
+//! keeps working when a combinator clones its argument per input.
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::Kleisli;
+
+struct Cache<A, B> {
+    entries: HashMap<A, (Rc<[B]>, u64)>,
+    // Entries by last use, only kept when the cache is bounded
+    order: BTreeMap<u64, A>,
+    capacity: Option<usize>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<A: Hash + Eq + Clone, B> Cache<A, B> {
+    fn new(capacity: Option<usize>) -> Self {
+        Cache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            capacity,
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn get(&mut self, a: &A) -> Option<Rc<[B]>> {
+        let Some((results, used)) = self.entries.get_mut(a) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        if self.capacity.is_some() {
+            self.tick += 1;
+            let key = self.order.remove(used).unwrap();
+            *used = self.tick;
+            self.order.insert(self.tick, key);
+        }
+        Some(results.clone())
+    }
+
+    fn insert(&mut self, a: A, results: Rc<[B]>) {
+        if let Some(capacity) = self.capacity {
+            if capacity == 0 {
+                return;
+            }
+            while self.entries.len() >= capacity {
+                let (_, oldest) = self.order.pop_first().unwrap();
+                self.entries.remove(&oldest);
+            }
+            self.tick += 1;
+            self.order.insert(self.tick, a.clone());
+        }
+        self.entries.insert(a, (results, self.tick));
+    }
+}
+
+/// An arrow caching its results for each input.
+pub struct Memoize<A, B, F> {
+    f: F,
+    cache: Rc<RefCell<Cache<A, B>>>,
+}
+
+impl<A, B, F: Clone> Clone for Memoize<A, B, F> {
+    fn clone(&self) -> Self {
+        Memoize {
+            f: self.f.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+/// Caches every result of `f`.
+pub fn memoize<A, F>(f: F) -> Memoize<A, F::Output, F>
+where
+    A: Hash + Eq + Clone,
+    F: Kleisli<A>,
+    F::Output: Clone,
+{
+    Memoize {
+        f,
+        cache: Rc::new(RefCell::new(Cache::new(None))),
+    }
+}
+
+/// Caches the results of `f` for the `capacity` most recently used inputs.
+pub fn memoize_lru<A, F>(f: F, capacity: usize) -> Memoize<A, F::Output, F>
+where
+    A: Hash + Eq + Clone,
+    F: Kleisli<A>,
+    F::Output: Clone,
+{
+    Memoize {
+        f,
+        cache: Rc::new(RefCell::new(Cache::new(Some(capacity)))),
+    }
+}
+
+impl<A, B, F> Memoize<A, B, F> {
+    /// How many applications were answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.cache.borrow().hits
+    }
+
+    /// How many applications ran the underlying arrow.
+    pub fn misses(&self) -> u64 {
+        self.cache.borrow().misses
+    }
+
+    /// Empties the cache, for all clones of the arrow.
+    pub fn clear(&self) {
+        let mut cache = self.cache.borrow_mut();
+        cache.entries.clear();
+        cache.order.clear();
+    }
+}
+
+impl<A, F> Kleisli<A> for Memoize<A, F::Output, F>
+where
+    A: Hash + Eq + Clone,
+    F: Kleisli<A>,
+    F::Output: Clone,
+{
+    type Output = F::Output;
+    type Iter = ApplyMemoize<F::Output>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        let cached = self.cache.borrow_mut().get(&a);
+        let results = match cached {
+            Some(results) => results,
+            None => {
+                let results: Rc<[F::Output]> = self.f.apply(a.clone()).collect();
+                self.cache.borrow_mut().insert(a, results.clone());
+                results
+            }
+        };
+        ApplyMemoize { results, next: 0 }
+    }
+}
+
+/// Yields clones of the cached results of one application.
+pub struct ApplyMemoize<B> {
+    results: Rc<[B]>,
+    next: usize,
+}
+
+impl<B> Clone for ApplyMemoize<B> {
+    fn clone(&self) -> Self {
+        ApplyMemoize {
+            results: self.results.clone(),
+            next: self.next,
+        }
+    }
+}
+
+impl<B: Clone> Iterator for ApplyMemoize<B> {
+    type Item = B;
+
+    fn next(&mut self) -> Option<B> {
+        let b = self.results.get(self.next)?.clone();
+        self.next += 1;
+        Some(b)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.results.len() - self.next;
+        (n, Some(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{kleisli_compose, kleisli_star};
+
+    // a diamond lattice, where every node is reached along many routes
+    const DB: &[(usize, usize)] = &[
+        (0, 1),
+        (0, 2),
+        (1, 3),
+        (2, 3),
+        (3, 4),
+        (3, 5),
+        (4, 6),
+        (5, 6),
+    ];
+
+    #[test]
+    fn lookups_run_once_per_input() {
+        let calls = RefCell::new(Vec::new());
+        let lookup = |x: usize| {
+            calls.borrow_mut().push(x);
+            DB.iter()
+                .filter(|e| e.0 == x)
+                .map(|e| e.1)
+                .collect::<Vec<_>>()
+        };
+        let next = memoize(lookup);
+        let mut three_hops =
+            kleisli_compose(next.clone(), kleisli_compose(next.clone(), next.clone()));
+        assert_eq!(three_hops.apply(0).collect::<Vec<_>>(), vec![4, 5, 4, 5]);
+        assert_eq!(*calls.borrow(), vec![0, 1, 3, 2]);
+        assert_eq!((next.hits(), next.misses()), (1, 4));
+
+        // the cache outlives the query
+        let mut reach = kleisli_star(next.clone());
+        assert_eq!(reach.apply(0).count(), 7);
+        assert_eq!(calls.borrow().len(), 7);
+
+        next.clear();
+        assert_eq!(next.clone().apply(0).count(), 2);
+        assert_eq!(calls.borrow().len(), 8);
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let calls = RefCell::new(0);
+        let mut double = memoize_lru(
+            |x: usize| {
+                *calls.borrow_mut() += 1;
+                vec![2 * x]
+            },
+            2,
+        );
+        for x in [1, 2, 1, 3, 1, 2] {
+            assert_eq!(double.apply(x).collect::<Vec<_>>(), vec![2 * x]);
+        }
+        // 3 evicts 2, then 2 evicts 3, while 1 stays hot
+        assert_eq!(*calls.borrow(), 4);
+        assert_eq!(double.hits(), 2);
+
+        let mut uncached = memoize_lru(|x: usize| vec![x], 0);
+        uncached.apply(1).count();
+        uncached.apply(1).count();
+        assert_eq!(uncached.misses(), 2);
+    }
+
+    #[test]
+    fn fork_cached_results() {
+        let mut next = memoize(|x: usize| vec![x, x + 1, x + 2]);
+        let mut it = next.apply(0);
+        it.next();
+        let fork = it.clone();
+        assert_eq!(it.collect::<Vec<_>>(), fork.collect::<Vec<_>>());
+        assert_eq!(next.apply(0).size_hint(), (3, Some(3)));
+    }
+}
@@ -0,0 +1,98 @@
+//! Parallel evaluation of composed arrows.
+//!
+//! The expansions by `g` of the results of `f` are independent of one
+//! another, so they can be spread over the rayon thread pool. Each
+//! worker gets its own clone of `g`.
+use rayon::prelude::*;
+
+use crate::{Kleisli, KleisliCompose};
+
+/// The order in which `par_apply` returns its results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParOrder {
+    /// The order sequential application would produce.
+    Preserve,
+    /// No particular order, so results are gathered per thread without
+    /// buffering each expansion separately.
+    Any,
+}
+
+impl<F, G> KleisliCompose<F, G> {
+    /// Applies `f` to `a`, then expands its results with `g` in
+    /// parallel.
+    pub fn par_apply<A>(&mut self, a: A, order: ParOrder) -> Vec<G::Output>
+    where
+        F: Kleisli<A>,
+        F::Output: Send,
+        G: Kleisli<F::Output> + Clone + Send,
+        G::Output: Send,
+    {
+        let bs: Vec<F::Output> = self.f.apply(a).collect();
+        let g = self.g.clone();
+        match order {
+            ParOrder::Preserve => bs
+                .into_par_iter()
+                .map_with(g, |g, b| g.apply(b).collect::<Vec<_>>())
+                .flatten_iter()
+                .collect(),
+            ParOrder::Any => bs
+                .into_par_iter()
+                .fold_with(Worker { g, out: Vec::new() }, |mut w, b| {
+                    w.out.extend(w.g.apply(b));
+                    w
+                })
+                .map(|w| w.out)
+                .reduce(Vec::new, |mut a, mut b| {
+                    // append the shorter half, wherever it came from
+                    if a.len() < b.len() {
+                        std::mem::swap(&mut a, &mut b);
+                    }
+                    a.append(&mut b);
+                    a
+                }),
+        }
+    }
+}
+
+// The state of one rayon split: its own `g` and the results so far.
+// Clones start with no results.
+struct Worker<G, C> {
+    g: G,
+    out: Vec<C>,
+}
+
+impl<G: Clone, C> Clone for Worker<G, C> {
+    fn clone(&self) -> Self {
+        Worker {
+            g: self.g.clone(),
+            out: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kleisli_compose;
+
+    fn divisors(n: u64) -> Vec<u64> {
+        (1..=n).filter(|d| n.is_multiple_of(*d)).collect()
+    }
+
+    #[test]
+    fn preserve_matches_sequential_order() {
+        let mut k = kleisli_compose(|n: u64| 1..n, divisors);
+        let sequential: Vec<_> = k.apply(200).collect();
+        assert_eq!(k.par_apply(200, ParOrder::Preserve), sequential);
+    }
+
+    #[test]
+    fn any_order_yields_the_same_bag() {
+        let mut k = kleisli_compose(|n: u64| 1..n, divisors);
+        let mut sequential: Vec<_> = k.apply(200).collect();
+        let mut parallel = k.par_apply(200, ParOrder::Any);
+        sequential.sort();
+        parallel.sort();
+        assert_eq!(parallel, sequential);
+    }
+}
@@ -0,0 +1,427 @@
+//! A parser for SPARQL style property paths.
+//!
+//! The grammar, loosest binding first:
+//!
+//! ```text
+//! path    := seq ('|' seq)*
+//! seq     := elt ('/' elt)*
+//! elt     := '^' elt | primary modifier?
+//! primary := label | '<' iri '>' | '(' path ')'
+//! modifier := '*' | '+' | '?' | '{' n '}' | '{' n ',' '}' | '{' n ',' m '}'
+//! ```
+//!
+//! Labels are runs of letters, digits and `_ : . -`, and whitespace
+//! between tokens is ignored. So `knows/(likes|^follows)+/name?` is
+//! `knows`, then one or more of `likes` or `follows` backwards, then
+//! optionally `name`.
+//!
+//! Chains of `/` and `|` are nested as balanced trees, so that long
+//! chains still make shallow expressions.
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::PathExpr;
+
+/// Why a path failed to parse, and where, as a byte range of the input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub span: Range<usize>,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The input ended where something else was expected.
+    UnexpectedEnd { expected: &'static str },
+    /// A character which cannot appear here.
+    Unexpected { found: char, expected: &'static str },
+    /// An opening `(`, `<` or `{` which is never closed.
+    Unclosed(char),
+    /// A repetition whose bounds are out of range or out of order.
+    InvalidBounds,
+    /// Parentheses nested more than 128 deep.
+    TooDeep,
+}
+
+// Bounds the recursion of the parser, which descends once per `(`
+const MAX_DEPTH: usize = 128;
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "expected {expected}, found the end of the path")?
+            }
+            ParseErrorKind::Unexpected { found, expected } => {
+                write!(f, "expected {expected}, found `{found}`")?
+            }
+            ParseErrorKind::Unclosed(open) => write!(f, "unclosed `{open}`")?,
+            ParseErrorKind::InvalidBounds => write!(f, "invalid repetition bounds")?,
+            ParseErrorKind::TooDeep => write!(f, "parentheses nested too deeply")?,
+        }
+        write!(f, " at {}..{}", self.span.start, self.span.end)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses a property path into a path expression over its labels.
+pub fn parse_path(src: &str) -> Result<PathExpr<String>, ParseError> {
+    let mut parser = Parser {
+        src,
+        pos: 0,
+        depth: 0,
+    };
+    let expr = parser.alt()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(c) => Err(parser.unexpected(c, "`|`, `/` or the end of the path")),
+    }
+}
+
+impl FromStr for PathExpr<String> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        parse_path(s)
+    }
+}
+
+struct Parser<'s> {
+    src: &'s str,
+    pos: usize,
+    // Open parentheses enclosing the current position
+    depth: usize,
+}
+
+fn is_label(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '.' | '-')
+}
+
+// Nests a non-empty chain of operands as a balanced tree, leaning left
+// like the operators do, so that its depth only grows with the
+// logarithm of its length.
+fn balance(
+    mut operands: Vec<PathExpr<String>>,
+    join: fn(PathExpr<String>, PathExpr<String>) -> PathExpr<String>,
+) -> PathExpr<String> {
+    if operands.len() == 1 {
+        return operands.pop().unwrap();
+    }
+    let right = operands.split_off(operands.len().div_ceil(2));
+    join(balance(operands, join), balance(right, join))
+}
+
+impl Parser<'_> {
+    // The next character after any whitespace.
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.src[self.pos..];
+        let trimmed = rest.trim_start();
+        self.pos += rest.len() - trimmed.len();
+        trimmed.chars().next()
+    }
+
+    fn bump(&mut self, c: char) {
+        self.pos += c.len_utf8();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump(c);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unexpected(&self, found: char, expected: &'static str) -> ParseError {
+        ParseError {
+            span: self.pos..self.pos + found.len_utf8(),
+            kind: ParseErrorKind::Unexpected { found, expected },
+        }
+    }
+
+    fn end(&self, expected: &'static str) -> ParseError {
+        ParseError {
+            span: self.pos..self.pos,
+            kind: ParseErrorKind::UnexpectedEnd { expected },
+        }
+    }
+
+    fn alt(&mut self) -> Result<PathExpr<String>, ParseError> {
+        let mut branches = vec![self.seq()?];
+        while self.eat('|') {
+            branches.push(self.seq()?);
+        }
+        Ok(balance(branches, PathExpr::alt))
+    }
+
+    fn seq(&mut self) -> Result<PathExpr<String>, ParseError> {
+        let mut steps = vec![self.elt()?];
+        while self.eat('/') {
+            steps.push(self.elt()?);
+        }
+        Ok(balance(steps, PathExpr::seq))
+    }
+
+    fn elt(&mut self) -> Result<PathExpr<String>, ParseError> {
+        // `^^p` is `p`, so only the parity of the carets matters
+        let mut inverse = false;
+        while self.eat('^') {
+            inverse = !inverse;
+        }
+        let primary = self.primary()?;
+        let expr = self.modifier(primary)?;
+        Ok(if inverse { expr.inverse() } else { expr })
+    }
+
+    fn primary(&mut self) -> Result<PathExpr<String>, ParseError> {
+        const EXPECTED: &str = "an edge label, `^` or `(`";
+        match self.peek() {
+            None => Err(self.end(EXPECTED)),
+            Some('(') => {
+                let open = self.pos;
+                if self.depth == MAX_DEPTH {
+                    return Err(ParseError {
+                        span: open..open + 1,
+                        kind: ParseErrorKind::TooDeep,
+                    });
+                }
+                self.bump('(');
+                self.depth += 1;
+                let parsed = self.alt()?;
+                self.depth -= 1;
+                match self.peek() {
+                    Some(')') => {
+                        self.bump(')');
+                        Ok(parsed)
+                    }
+                    Some(c) => Err(self.unexpected(c, "`|`, `/` or `)`")),
+                    None => Err(ParseError {
+                        span: open..self.pos,
+                        kind: ParseErrorKind::Unclosed('('),
+                    }),
+                }
+            }
+            Some('<') => {
+                let open = self.pos;
+                let rest = &self.src[open + 1..];
+                match rest.find('>') {
+                    Some(len) => {
+                        self.pos = open + 1 + len + 1;
+                        Ok(PathExpr::edge(rest[..len].to_string()))
+                    }
+                    None => Err(ParseError {
+                        span: open..self.src.len(),
+                        kind: ParseErrorKind::Unclosed('<'),
+                    }),
+                }
+            }
+            Some(c) if is_label(c) => {
+                let rest = &self.src[self.pos..];
+                let len = rest.find(|c| !is_label(c)).unwrap_or(rest.len());
+                self.pos += len;
+                Ok(PathExpr::edge(rest[..len].to_string()))
+            }
+            Some(c) => Err(self.unexpected(c, EXPECTED)),
+        }
+    }
+
+    fn modifier(&mut self, expr: PathExpr<String>) -> Result<PathExpr<String>, ParseError> {
+        match self.peek() {
+            Some('*') => {
+                self.bump('*');
+                Ok(expr.star())
+            }
+            Some('+') => {
+                self.bump('+');
+                Ok(expr.plus())
+            }
+            Some('?') => {
+                self.bump('?');
+                Ok(expr.opt())
+            }
+            Some('{') => {
+                let open = self.pos;
+                self.bump('{');
+                let min = self.number(open)?;
+                let max = if self.eat(',') {
+                    match self.peek() {
+                        Some('}') => None,
+                        _ => Some(self.number(open)?),
+                    }
+                } else {
+                    Some(min)
+                };
+                match self.peek() {
+                    Some('}') => self.bump('}'),
+                    Some(c) => return Err(self.unexpected(c, "`,` or `}`")),
+                    None => {
+                        return Err(ParseError {
+                            span: open..self.pos,
+                            kind: ParseErrorKind::Unclosed('{'),
+                        })
+                    }
+                }
+                if max.is_some_and(|max| max < min) {
+                    return Err(ParseError {
+                        span: open..self.pos,
+                        kind: ParseErrorKind::InvalidBounds,
+                    });
+                }
+                Ok(expr.repeat(min, max))
+            }
+            _ => Ok(expr),
+        }
+    }
+
+    fn number(&mut self, open: usize) -> Result<usize, ParseError> {
+        let start = match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.pos,
+            Some(c) => return Err(self.unexpected(c, "a number")),
+            None => {
+                return Err(ParseError {
+                    span: open..self.pos,
+                    kind: ParseErrorKind::Unclosed('{'),
+                })
+            }
+        };
+        let rest = &self.src[start..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        self.pos += len;
+        rest[..len].parse().map_err(|_| ParseError {
+            span: start..self.pos,
+            kind: ParseErrorKind::InvalidBounds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(label: &str) -> PathExpr<String> {
+        PathExpr::edge(label.to_string())
+    }
+
+    #[test]
+    fn parses_property_paths() {
+        assert_eq!(
+            parse_path("knows/(likes|^follows)+/name?"),
+            Ok(edge("knows")
+                .seq(edge("likes").alt(edge("follows").inverse()).plus())
+                .seq(edge("name").opt()))
+        );
+        assert_eq!(
+            " a | b / c ".parse(),
+            Ok(edge("a").alt(edge("b").seq(edge("c"))))
+        );
+        assert_eq!(
+            parse_path("^<http://xmlns.com/foaf/0.1/knows>*"),
+            Ok(edge("http://xmlns.com/foaf/0.1/knows").star().inverse())
+        );
+        assert_eq!(
+            parse_path("foaf:knows{2}/p{1,}/q{0,3}"),
+            Ok(edge("foaf:knows")
+                .repeat(2, Some(2))
+                .seq(edge("p").repeat(1, None))
+                .seq(edge("q").repeat(0, Some(3))))
+        );
+        assert_eq!(
+            parse_path("^^a/^ ^ ^b"),
+            Ok(edge("a").seq(edge("b").inverse()))
+        );
+        assert_eq!(parse_path(&("^".repeat(100_000) + "a")), Ok(edge("a")));
+        assert_eq!(
+            parse_path("a/b/c/d"),
+            Ok(edge("a").seq(edge("b")).seq(edge("c").seq(edge("d"))))
+        );
+        // long chains are shallow enough to compare and clone
+        for op in ["/", "|"] {
+            let long = parse_path(&(format!("a{op}").repeat(200_000) + "a")).unwrap();
+            assert_eq!(long.clone(), long);
+        }
+    }
+
+    #[test]
+    fn parsed_paths_compile() {
+        use crate::{compile_path, Direction, Kleisli};
+
+        let graph = [("a", "p", "b"), ("b", "q", "c"), ("d", "q", "c")];
+        let lookup = |label: &String, dir: Direction, node: &'static str| {
+            graph
+                .iter()
+                .filter(move |e| e.1 == label)
+                .filter_map(move |&(s, _, o)| match dir {
+                    Direction::Forward => (s == node).then_some(o),
+                    Direction::Backward => (o == node).then_some(s),
+                })
+                .collect::<Vec<_>>()
+        };
+        let expr = parse_path("p/q/^q").unwrap();
+        let mut arrow = compile_path(&expr, lookup);
+        assert_eq!(arrow.apply("a").collect::<Vec<_>>(), vec!["b", "d"]);
+    }
+
+    fn error(src: &str) -> (Range<usize>, ParseErrorKind) {
+        let e = parse_path(src).unwrap_err();
+        (e.span, e.kind)
+    }
+
+    #[test]
+    fn reports_error_spans() {
+        assert_eq!(
+            error("knows/"),
+            (
+                6..6,
+                ParseErrorKind::UnexpectedEnd {
+                    expected: "an edge label, `^` or `(`"
+                }
+            )
+        );
+        assert_eq!(error("a/(b|c"), (2..6, ParseErrorKind::Unclosed('(')));
+        assert_eq!(
+            error("a b"),
+            (
+                2..3,
+                ParseErrorKind::Unexpected {
+                    found: 'b',
+                    expected: "`|`, `/` or the end of the path"
+                }
+            )
+        );
+        assert_eq!(error("<http://x"), (0..9, ParseErrorKind::Unclosed('<')));
+        assert_eq!(error("p{3,1}"), (1..6, ParseErrorKind::InvalidBounds));
+        assert_eq!(
+            error("p{0,99999999999999999999}"),
+            (4..24, ParseErrorKind::InvalidBounds)
+        );
+        assert!(parse_path("p{0,70000}").is_ok());
+        assert_eq!(
+            error("p{x}"),
+            (
+                2..3,
+                ParseErrorKind::Unexpected {
+                    found: 'x',
+                    expected: "a number"
+                }
+            )
+        );
+        let deep = "(".repeat(MAX_DEPTH + 1) + "a" + &")".repeat(MAX_DEPTH + 1);
+        assert_eq!(
+            error(&deep),
+            (MAX_DEPTH..MAX_DEPTH + 1, ParseErrorKind::TooDeep)
+        );
+        assert!(parse_path(&deep[1..deep.len() - 1]).is_ok());
+        let deeper = "(".repeat(100_000);
+        assert_eq!(error(&deeper).1, ParseErrorKind::TooDeep);
+        assert_eq!(
+            parse_path("a|*").unwrap_err().to_string(),
+            "expected an edge label, `^` or `(`, found `*` at 2..3"
+        );
+    }
+}
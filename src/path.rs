@@ -0,0 +1,373 @@
+//! Regular path queries.
+//!
+//! A `PathExpr` describes the routes through an edge labelled graph
+//! which a query follows, and `compile_path` turns it into a Kleisli
+//! arrow from start nodes to end nodes, built from the combinators of
+//! this crate. The graph is only known through a lookup callback,
+//! which finds the neighbours of a node along edges with a given label
+//! in a given direction.
+use std::cell::RefCell;
+use std::hash::Hash;
+use std::mem;
+use std::rc::Rc;
+
+use crate::{
+    kleisli_compose_all, kleisli_or, kleisli_plus, kleisli_repeat, kleisli_star, ret, BiArrow,
+    BoxKleisli,
+};
+
+/// A regular expression over edge labels.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathExpr<L> {
+    /// The empty path, from each node to itself.
+    Empty,
+    /// One edge with the label, followed forwards.
+    Edge(L),
+    /// The path followed backwards, from its end to its start (`^p`).
+    Inverse(Box<PathExpr<L>>),
+    /// One path and then the other (`p/q`).
+    Seq(Box<PathExpr<L>>, Box<PathExpr<L>>),
+    /// Either path (`p|q`).
+    Alt(Box<PathExpr<L>>, Box<PathExpr<L>>),
+    /// The path zero or more times (`p*`).
+    Star(Box<PathExpr<L>>),
+    /// The path one or more times (`p+`).
+    Plus(Box<PathExpr<L>>),
+    /// The path zero or one times (`p?`).
+    Opt(Box<PathExpr<L>>),
+    /// The path between `min` and `max` times, without an upper bound
+    /// for `None` (`p{n,m}`).
+    Repeat {
+        expr: Box<PathExpr<L>>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+impl<L> PathExpr<L> {
+    pub fn empty() -> Self {
+        PathExpr::Empty
+    }
+
+    pub fn edge(label: L) -> Self {
+        PathExpr::Edge(label)
+    }
+
+    pub fn inverse(self) -> Self {
+        PathExpr::Inverse(Box::new(self))
+    }
+
+    pub fn seq(self, next: Self) -> Self {
+        PathExpr::Seq(Box::new(self), Box::new(next))
+    }
+
+    pub fn alt(self, other: Self) -> Self {
+        PathExpr::Alt(Box::new(self), Box::new(other))
+    }
+
+    pub fn star(self) -> Self {
+        PathExpr::Star(Box::new(self))
+    }
+
+    pub fn plus(self) -> Self {
+        PathExpr::Plus(Box::new(self))
+    }
+
+    pub fn opt(self) -> Self {
+        PathExpr::Opt(Box::new(self))
+    }
+
+    pub fn repeat(self, min: usize, max: Option<usize>) -> Self {
+        PathExpr::Repeat {
+            expr: Box::new(self),
+            min,
+            max,
+        }
+    }
+
+    // The operands of a chain of `/` or `|` such as `p/q/r`, whichever
+    // `self` is, from left to right however the chain is nested.
+    pub(crate) fn operands(&self) -> Vec<&Self> {
+        let same = |e: &Self| mem::discriminant(e) == mem::discriminant(self);
+        let mut operands = Vec::new();
+        let mut pending = vec![self];
+        while let Some(e) = pending.pop() {
+            match e {
+                PathExpr::Seq(p, q) | PathExpr::Alt(p, q) if same(e) => {
+                    pending.push(q);
+                    pending.push(p);
+                }
+                _ => operands.push(e),
+            }
+        }
+        operands
+    }
+
+    // Moves the subexpressions onto `stack`, leaving `Empty` behind.
+    fn take_children(&mut self, stack: &mut Vec<Self>) {
+        match self {
+            PathExpr::Empty | PathExpr::Edge(_) => {}
+            PathExpr::Inverse(p)
+            | PathExpr::Star(p)
+            | PathExpr::Plus(p)
+            | PathExpr::Opt(p)
+            | PathExpr::Repeat { expr: p, .. } => stack.push(mem::replace(p, PathExpr::Empty)),
+            PathExpr::Seq(p, q) | PathExpr::Alt(p, q) => {
+                stack.push(mem::replace(p, PathExpr::Empty));
+                stack.push(mem::replace(q, PathExpr::Empty));
+            }
+        }
+    }
+}
+
+// Take nested expressions apart one node at a time, rather than
+// recursively, so that dropping a deep expression cannot overflow the
+// stack.
+impl<L> Drop for PathExpr<L> {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut expr) = stack.pop() {
+            expr.take_children(&mut stack);
+        }
+    }
+}
+
+/// The direction in which an edge is followed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the subject of the edge to its object.
+    Forward,
+    /// From the object of the edge to its subject.
+    Backward,
+}
+
+impl Direction {
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+}
+
+/// Compiles a path expression to an arrow from each start node to the
+/// nodes at the end of the matching routes. `lookup(label, direction,
+/// node)` gives the neighbours of `node` along edges with `label`.
+///
+/// Closures (`*`, `+` and unbounded repetition) yield each node once
+/// per start node, so they terminate on cyclic graphs; the other
+/// operators keep bag semantics, as `kleisli_compose` does.
+///
+/// Chains of `/` and `|` compile to single n-ary arrows however long
+/// they are, while the other operators nest an arrow per level, so
+/// their depth is limited by the stack.
+pub fn compile_path<'a, N, L, E, I>(expr: &PathExpr<L>, lookup: E) -> BoxKleisli<'a, N, N>
+where
+    N: Clone + Eq + Hash + 'a,
+    L: Clone + 'a,
+    E: FnMut(&L, Direction, N) -> I + 'a,
+    I: IntoIterator<Item = N>,
+    I::IntoIter: 'a,
+{
+    let lookup = Rc::new(RefCell::new(lookup));
+    compile(expr, Direction::Forward, &lookup)
+}
+
+/// Compiles a path expression to a pair of arrows, one from the start
+/// nodes to the end nodes and the other back, sharing `lookup`.
+#[allow(clippy::type_complexity)]
+pub fn compile_path_bi<'a, N, L, E, I>(
+    expr: &PathExpr<L>,
+    lookup: E,
+) -> BiArrow<BoxKleisli<'a, N, N>, BoxKleisli<'a, N, N>>
+where
+    N: Clone + Eq + Hash + 'a,
+    L: Clone + 'a,
+    E: FnMut(&L, Direction, N) -> I + 'a,
+    I: IntoIterator<Item = N>,
+    I::IntoIter: 'a,
+{
+    let lookup = Rc::new(RefCell::new(lookup));
+    BiArrow::new(
+        compile(expr, Direction::Forward, &lookup),
+        compile(expr, Direction::Backward, &lookup),
+    )
+}
+
+fn compile<'a, N, L, E, I>(
+    expr: &PathExpr<L>,
+    dir: Direction,
+    lookup: &Rc<RefCell<E>>,
+) -> BoxKleisli<'a, N, N>
+where
+    N: Clone + Eq + Hash + 'a,
+    L: Clone + 'a,
+    E: FnMut(&L, Direction, N) -> I + 'a,
+    I: IntoIterator<Item = N>,
+    I::IntoIter: 'a,
+{
+    match expr {
+        PathExpr::Empty => BoxKleisli::new(ret::<N>),
+        PathExpr::Edge(label) => {
+            let label = label.clone();
+            let lookup = lookup.clone();
+            BoxKleisli::new(move |n: N| (lookup.borrow_mut())(&label, dir, n))
+        }
+        PathExpr::Inverse(p) => compile(p, dir.reverse(), lookup),
+        // Followed backwards, the steps of a sequence come in reverse.
+        PathExpr::Seq(..) => {
+            let mut steps: Vec<_> = expr
+                .operands()
+                .into_iter()
+                .map(|p| compile(p, dir, lookup))
+                .collect();
+            if dir == Direction::Backward {
+                steps.reverse();
+            }
+            BoxKleisli::new(kleisli_compose_all(steps))
+        }
+        PathExpr::Alt(..) => BoxKleisli::new(kleisli_or(
+            expr.operands().into_iter().map(|p| compile(p, dir, lookup)),
+        )),
+        PathExpr::Star(p) => BoxKleisli::new(kleisli_star(compile(p, dir, lookup))),
+        PathExpr::Plus(p) => BoxKleisli::new(kleisli_plus(compile(p, dir, lookup))),
+        PathExpr::Opt(p) => BoxKleisli::new(kleisli_repeat(compile(p, dir, lookup), 0, Some(1))),
+        PathExpr::Repeat { expr, min, max } => {
+            BoxKleisli::new(kleisli_repeat(compile(expr, dir, lookup), *min, *max))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Kleisli;
+
+    const GRAPH: &[(&str, &str, &str)] = &[
+        ("alice", "knows", "bob"),
+        ("bob", "knows", "carol"),
+        ("carol", "knows", "alice"),
+        ("carol", "knows", "dave"),
+        ("bob", "likes", "erin"),
+        ("erin", "follows", "alice"),
+        ("dave", "name", "Dave"),
+    ];
+
+    fn lookup(label: &&str, dir: Direction, node: &'static str) -> Vec<&'static str> {
+        GRAPH
+            .iter()
+            .filter_map(|&(s, l, o)| match dir {
+                _ if l != *label => None,
+                Direction::Forward if s == node => Some(o),
+                Direction::Backward if o == node => Some(s),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn eval(expr: &PathExpr<&'static str>, start: &'static str) -> Vec<&'static str> {
+        let mut arrow = compile_path(expr, lookup);
+        let mut res: Vec<_> = arrow.apply(start).collect();
+        res.sort();
+        res
+    }
+
+    fn edge(label: &'static str) -> PathExpr<&'static str> {
+        PathExpr::edge(label)
+    }
+
+    #[test]
+    fn sequence_and_alternation() {
+        assert_eq!(
+            eval(&edge("knows").seq(edge("knows")), "alice"),
+            vec!["carol"]
+        );
+        assert_eq!(
+            eval(&edge("knows").alt(edge("likes")), "bob"),
+            vec!["carol", "erin"]
+        );
+        assert_eq!(eval(&edge("knows").opt(), "dave"), vec!["dave"]);
+    }
+
+    #[test]
+    fn closures_terminate_on_cycles() {
+        assert_eq!(
+            eval(&edge("knows").star(), "alice"),
+            vec!["alice", "bob", "carol", "dave"]
+        );
+        assert_eq!(
+            eval(&edge("knows").plus().seq(edge("name")), "alice"),
+            vec!["Dave"]
+        );
+        assert_eq!(
+            eval(&edge("knows").repeat(2, None), "alice"),
+            vec!["alice", "bob", "carol", "dave"]
+        );
+        assert_eq!(
+            eval(&edge("knows").repeat(1, Some(2)), "alice"),
+            vec!["bob", "carol"]
+        );
+    }
+
+    #[test]
+    fn unbounded_repetition_yields_each_node_once() {
+        const DIAMOND: &[(u32, u32)] = &[(1, 2), (1, 3), (2, 4), (3, 4)];
+        let lookup =
+            |_: &&str, _: Direction, n: u32| DIAMOND.iter().filter(move |e| e.0 == n).map(|e| e.1);
+        let mut plus = compile_path(&edge("k").plus(), lookup);
+        let mut at_least_one = compile_path(&edge("k").repeat(1, None), lookup);
+        assert_eq!(at_least_one.apply(1).collect::<Vec<_>>(), vec![2, 4, 3]);
+        assert_eq!(
+            at_least_one.apply(1).collect::<Vec<_>>(),
+            plus.apply(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn inverse_reverses_the_route() {
+        assert_eq!(eval(&edge("name").inverse(), "Dave"), vec!["dave"]);
+        let route = edge("likes").seq(edge("follows"));
+        assert_eq!(eval(&route, "bob"), vec!["alice"]);
+        assert_eq!(eval(&route.inverse(), "alice"), vec!["bob"]);
+        assert_eq!(
+            eval(&edge("knows").seq(edge("likes")).inverse().plus(), "erin"),
+            vec!["alice"]
+        );
+    }
+
+    #[test]
+    fn bidirectional_compilation() {
+        let mut path = compile_path_bi(&edge("likes").seq(edge("follows")), lookup);
+        assert_eq!(path.apply("bob").collect::<Vec<_>>(), vec!["alice"]);
+        assert_eq!(
+            path.apply_backward("alice").collect::<Vec<_>>(),
+            vec!["bob"]
+        );
+        let mut back = path.inverse();
+        assert_eq!(back.apply("alice").collect::<Vec<_>>(), vec!["bob"]);
+    }
+
+    #[test]
+    fn empty_path_stays_put() {
+        assert_eq!(eval(&PathExpr::empty(), "bob"), vec!["bob"]);
+        assert_eq!(
+            eval(&edge("knows").seq(PathExpr::empty()).inverse(), "bob"),
+            vec!["alice"]
+        );
+    }
+
+    #[test]
+    fn long_chains_compile_flat() {
+        let step = |_: &&str, _: Direction, n: usize| [n + 1];
+        let alts = (1..20_000).fold(edge("a"), |e, _| e.alt(edge("a")));
+        assert_eq!(compile_path(&alts, step).apply(0).count(), 20_000);
+
+        let seqs = (1..20_000).fold(edge("a"), |e, _| e.seq(edge("a")));
+        let mut path = compile_path_bi(&seqs, step);
+        assert_eq!(path.apply(0).collect::<Vec<_>>(), vec![20_000]);
+
+        // deeply nested operators are still taken apart without recursion
+        drop((0..1_000_000).fold(edge("a"), |e, _| e.star()));
+    }
+}
@@ -0,0 +1,362 @@
+//! Inspectable query plans.
+//!
+//! A composed arrow is an opaque tree of closures. Building it from
+//! `Planned` arrows instead, with the `planned_*` counterparts of the
+//! combinators, records the shape of the tree alongside it, as a `Plan`
+//! whose nodes count how often they are applied and how many results
+//! they yield. `explain` renders the plan with its counters as indented
+//! text, and `to_dot` as a Graphviz graph.
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt::{self, Write};
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::{
+    kleisli_choice, kleisli_compose, kleisli_distinct, kleisli_plus, kleisli_repeat, kleisli_star,
+    Kleisli, KleisliChoice, KleisliCompose, KleisliDistinct, KleisliRepeat, KleisliStar,
+};
+
+/// The operation at a plan node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlanOp {
+    Lookup(String),
+    Compose,
+    Choice,
+    Star,
+    Plus,
+    Repeat { min: usize, max: Option<usize> },
+    Distinct,
+}
+
+impl fmt::Display for PlanOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanOp::Lookup(name) => write!(f, "Lookup({name})"),
+            PlanOp::Compose => write!(f, "Compose"),
+            PlanOp::Choice => write!(f, "Choice"),
+            PlanOp::Star => write!(f, "Star"),
+            PlanOp::Plus => write!(f, "Plus"),
+            PlanOp::Repeat {
+                min,
+                max: Some(max),
+            } => write!(f, "Repeat{{{min},{max}}}"),
+            PlanOp::Repeat { min, max: None } => write!(f, "Repeat{{{min},}}"),
+            PlanOp::Distinct => write!(f, "Distinct"),
+        }
+    }
+}
+
+struct Node {
+    op: PlanOp,
+    children: Vec<Plan>,
+    calls: Cell<u64>,
+    rows: Cell<u64>,
+}
+
+/// A node of a plan tree. Clones share their counters, as do the clones
+/// of the arrow carrying the plan.
+#[derive(Clone)]
+pub struct Plan(Rc<Node>);
+
+impl Plan {
+    fn new(op: PlanOp, children: Vec<Plan>) -> Self {
+        Plan(Rc::new(Node {
+            op,
+            children,
+            calls: Cell::new(0),
+            rows: Cell::new(0),
+        }))
+    }
+
+    pub fn op(&self) -> &PlanOp {
+        &self.0.op
+    }
+
+    pub fn children(&self) -> &[Plan] {
+        &self.0.children
+    }
+
+    /// How many times the arrow at this node has been applied.
+    pub fn calls(&self) -> u64 {
+        self.0.calls.get()
+    }
+
+    /// How many results the arrow at this node has yielded.
+    pub fn rows(&self) -> u64 {
+        self.0.rows.get()
+    }
+
+    /// Zeroes the counters of this node and all below it.
+    pub fn reset(&self) {
+        self.0.calls.set(0);
+        self.0.rows.set(0);
+        for child in self.children() {
+            child.reset();
+        }
+    }
+
+    /// Renders the plan as an indented tree, one node per line.
+    pub fn explain(&self) -> String {
+        let mut out = String::new();
+        self.explain_into(&mut out, 0);
+        out
+    }
+
+    fn explain_into(&self, out: &mut String, depth: usize) {
+        let _ = writeln!(
+            out,
+            "{:indent$}{} (calls: {}, rows: {})",
+            "",
+            self.op(),
+            self.calls(),
+            self.rows(),
+            indent = 2 * depth
+        );
+        for child in self.children() {
+            child.explain_into(out, depth + 1);
+        }
+    }
+
+    /// Renders the plan as a Graphviz `digraph`.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph plan {\n");
+        self.dot_into(&mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    // Writes this node as `n{next}` and its children after it
+    fn dot_into(&self, out: &mut String, next: &mut usize) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = self
+            .op()
+            .to_string()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"");
+        let _ = writeln!(
+            out,
+            "  n{id} [label=\"{label}\\ncalls: {}, rows: {}\"];",
+            self.calls(),
+            self.rows()
+        );
+        for child in self.children() {
+            let child = child.dot_into(out, next);
+            let _ = writeln!(out, "  n{id} -> n{child};");
+        }
+        id
+    }
+}
+
+impl fmt::Debug for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.explain())
+    }
+}
+
+/// An arrow carrying the plan it was built from.
+#[derive(Clone)]
+pub struct Planned<F> {
+    f: F,
+    plan: Plan,
+}
+
+/// A named base arrow, the leaf of a plan.
+pub fn kleisli_lookup<A, F>(name: impl Into<String>, f: F) -> Planned<F>
+where
+    F: Kleisli<A>,
+{
+    Planned {
+        f,
+        plan: Plan::new(PlanOp::Lookup(name.into()), Vec::new()),
+    }
+}
+
+impl<F> Planned<F> {
+    pub fn plan(&self) -> &Plan {
+        &self.plan
+    }
+
+    pub fn explain(&self) -> String {
+        self.plan.explain()
+    }
+
+    pub fn to_dot(&self) -> String {
+        self.plan.to_dot()
+    }
+}
+
+fn wrap<F>(op: PlanOp, children: Vec<Plan>, f: F) -> Planned<F> {
+    Planned {
+        f,
+        plan: Plan::new(op, children),
+    }
+}
+
+/// `f >=> g`, planned.
+pub fn planned_compose<A, F, G>(
+    f: Planned<F>,
+    g: Planned<G>,
+) -> Planned<KleisliCompose<Planned<F>, Planned<G>>>
+where
+    F: Kleisli<A>,
+    G: Kleisli<F::Output>,
+{
+    let children = vec![f.plan.clone(), g.plan.clone()];
+    wrap(PlanOp::Compose, children, kleisli_compose(f, g))
+}
+
+/// The results of `f` followed by those of `g`, planned.
+pub fn planned_choice<A, F, G>(
+    f: Planned<F>,
+    g: Planned<G>,
+) -> Planned<KleisliChoice<Planned<F>, Planned<G>>>
+where
+    A: Clone,
+    F: Kleisli<A>,
+    G: Kleisli<A, Output = F::Output>,
+{
+    let children = vec![f.plan.clone(), g.plan.clone()];
+    wrap(PlanOp::Choice, children, kleisli_choice(f, g))
+}
+
+pub fn planned_star<A, F>(f: Planned<F>) -> Planned<KleisliStar<Planned<F>>>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    let children = vec![f.plan.clone()];
+    wrap(PlanOp::Star, children, kleisli_star(f))
+}
+
+pub fn planned_plus<A, F>(f: Planned<F>) -> Planned<KleisliStar<Planned<F>>>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    let children = vec![f.plan.clone()];
+    wrap(PlanOp::Plus, children, kleisli_plus(f))
+}
+
+pub fn planned_repeat<A, F>(
+    f: Planned<F>,
+    min: usize,
+    max: Option<usize>,
+) -> Planned<KleisliRepeat<Planned<F>>>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    let children = vec![f.plan.clone()];
+    let op = PlanOp::Repeat { min, max };
+    wrap(op, children, kleisli_repeat(f, min, max))
+}
+
+pub fn planned_distinct<A, F>(
+    f: Planned<F>,
+) -> Planned<KleisliDistinct<Planned<F>, HashSet<F::Output>>>
+where
+    F: Kleisli<A>,
+    F::Output: Hash + Eq + Clone,
+{
+    let children = vec![f.plan.clone()];
+    wrap(PlanOp::Distinct, children, kleisli_distinct(f))
+}
+
+impl<A, F: Kleisli<A>> Kleisli<A> for Planned<F> {
+    type Output = F::Output;
+    type Iter = ApplyPlanned<F::Iter>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        let node = &self.plan.0;
+        node.calls.set(node.calls.get() + 1);
+        ApplyPlanned {
+            iter: self.f.apply(a),
+            plan: self.plan.clone(),
+        }
+    }
+}
+
+/// Counts the results of a planned arrow as they are yielded.
+#[derive(Clone)]
+pub struct ApplyPlanned<I> {
+    iter: I,
+    plan: Plan,
+}
+
+impl<I: Iterator> Iterator for ApplyPlanned<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let item = self.iter.next()?;
+        let node = &self.plan.0;
+        node.rows.set(node.rows.get() + 1);
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB: &[(usize, usize)] = &[(1, 2), (1, 3), (2, 4), (3, 4), (4, 5)];
+
+    fn next() -> Planned<impl Fn(usize) -> Vec<usize> + Clone> {
+        kleisli_lookup("next", |x: usize| {
+            DB.iter()
+                .filter(|e| e.0 == x)
+                .map(|e| e.1)
+                .collect::<Vec<_>>()
+        })
+    }
+
+    #[test]
+    fn counts_calls_and_rows() {
+        let mut two_hops = planned_compose(next(), next());
+        assert_eq!(two_hops.apply(1).collect::<Vec<_>>(), vec![4, 4]);
+        assert_eq!(
+            two_hops.explain(),
+            "Compose (calls: 1, rows: 2)\n  \
+             Lookup(next) (calls: 1, rows: 2)\n  \
+             Lookup(next) (calls: 2, rows: 2)\n"
+        );
+
+        two_hops.plan().reset();
+        assert_eq!(two_hops.plan().children()[1].calls(), 0);
+    }
+
+    #[test]
+    fn nested_plan() {
+        let mut reach = planned_plus(planned_distinct(planned_choice(
+            next(),
+            planned_compose(next(), next()),
+        )));
+        let mut found: Vec<_> = reach.apply(1).collect();
+        found.sort();
+        assert_eq!(found, vec![2, 3, 4, 5]);
+
+        let plan = reach.plan();
+        assert_eq!(plan.op(), &PlanOp::Plus);
+        assert_eq!(plan.rows(), 4);
+        let distinct = &plan.children()[0];
+        assert_eq!(distinct.op(), &PlanOp::Distinct);
+        assert_eq!(distinct.calls(), 5);
+        assert!(distinct.rows() < distinct.children()[0].rows());
+    }
+
+    #[test]
+    fn dot_output() {
+        let reach = planned_repeat(next(), 1, Some(2));
+        assert_eq!(
+            reach.to_dot(),
+            "digraph plan {\n  \
+             n0 [label=\"Repeat{1,2}\\ncalls: 0, rows: 0\"];\n  \
+             n1 [label=\"Lookup(next)\\ncalls: 0, rows: 0\"];\n  \
+             n0 -> n1;\n\
+             }\n"
+        );
+        let quoted = kleisli_lookup("say \"hi\"", |x: usize| vec![x]);
+        assert!(quoted.to_dot().contains(r#"label="Lookup(say \"hi\")\n"#));
+    }
+}
@@ -0,0 +1,296 @@
+//! Kleisli composition over asynchronous streams.
+//!
+//! The asynchronous counterpart of `KleisliCompose`: arrows produce a
+//! `futures::Stream` rather than an iterator. The composed stream
+//! either expands each intermediate result in turn, preserving order,
+//! or keeps several expansions in flight at once and yields results as
+//! they become ready.
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::stream::{SelectAll, Stream, StreamExt};
+
+/// An asynchronous Kleisli arrow `A -> Stream<Output>`. Closures
+/// returning a stream are arrows, as are composed arrows.
+pub trait AsyncKleisli<A> {
+    type Output;
+    type Stream: Stream<Item = Self::Output>;
+
+    fn apply(&mut self, a: A) -> Self::Stream;
+}
+
+impl<A, S: Stream, F: FnMut(A) -> S> AsyncKleisli<A> for F {
+    type Output = S::Item;
+    type Stream = S;
+
+    fn apply(&mut self, a: A) -> S {
+        self(a)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Flatten {
+    Sequential,
+    Unordered(Option<usize>),
+}
+
+#[derive(Clone)]
+pub struct AsyncKleisliCompose<F, G> {
+    f: F,
+    g: G,
+    flatten: Flatten,
+}
+
+// Composition of asynchronous Kleisli arrows, in order
+pub fn async_kleisli_compose<A, F, G>(f: F, g: G) -> AsyncKleisliCompose<F, G>
+where
+    F: AsyncKleisli<A>,
+    G: AsyncKleisli<F::Output>,
+{
+    AsyncKleisliCompose {
+        f,
+        g,
+        flatten: Flatten::Sequential,
+    }
+}
+
+/// Composition which polls up to `limit` expansions by `g` at once
+/// (all of them for `None`), yielding results in the order they are
+/// ready rather than the order of the results of `f`.
+pub fn async_kleisli_compose_unordered<A, F, G>(
+    f: F,
+    g: G,
+    limit: impl Into<Option<usize>>,
+) -> AsyncKleisliCompose<F, G>
+where
+    F: AsyncKleisli<A>,
+    G: AsyncKleisli<F::Output>,
+{
+    AsyncKleisliCompose {
+        f,
+        g,
+        flatten: Flatten::Unordered(limit.into()),
+    }
+}
+
+impl<A, F, G> AsyncKleisli<A> for AsyncKleisliCompose<F, G>
+where
+    F: AsyncKleisli<A>,
+    G: AsyncKleisli<F::Output> + Clone,
+{
+    type Output = G::Output;
+    type Stream = ApplyAsyncKleisliCompose<A, F, G>;
+
+    fn apply(&mut self, a: A) -> Self::Stream {
+        ApplyAsyncKleisliCompose {
+            outer: Some(Box::pin(self.f.apply(a))),
+            inner: None,
+            active: SelectAll::new(),
+            g: self.g.clone(),
+            flatten: self.flatten,
+        }
+    }
+}
+
+pub struct ApplyAsyncKleisliCompose<A, F: AsyncKleisli<A>, G: AsyncKleisli<F::Output>> {
+    outer: Option<Pin<Box<F::Stream>>>,
+    inner: Option<Pin<Box<G::Stream>>>,
+    active: SelectAll<Pin<Box<G::Stream>>>,
+    g: G,
+    flatten: Flatten,
+}
+
+// The streams are boxed and `g` is never pinned, so nothing is
+// structurally pinned.
+impl<A, F: AsyncKleisli<A>, G: AsyncKleisli<F::Output>> Unpin
+    for ApplyAsyncKleisliCompose<A, F, G>
+{
+}
+
+impl<A, F: AsyncKleisli<A>, G: AsyncKleisli<F::Output>> ApplyAsyncKleisliCompose<A, F, G> {
+    pub fn new(a: A, kc: AsyncKleisliCompose<F, G>) -> Self {
+        let AsyncKleisliCompose { mut f, g, flatten } = kc;
+        ApplyAsyncKleisliCompose {
+            outer: Some(Box::pin(f.apply(a))),
+            inner: None,
+            active: SelectAll::new(),
+            g,
+            flatten,
+        }
+    }
+
+    fn poll_sequential(&mut self, cx: &mut Context<'_>) -> Poll<Option<G::Output>> {
+        loop {
+            if let Some(inner) = &mut self.inner {
+                match inner.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(c)) => return Poll::Ready(Some(c)),
+                    Poll::Ready(None) => self.inner = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            let Some(outer) = &mut self.outer else {
+                return Poll::Ready(None);
+            };
+            match outer.as_mut().poll_next(cx) {
+                Poll::Ready(Some(b)) => self.inner = Some(Box::pin(self.g.apply(b))),
+                Poll::Ready(None) => self.outer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_unordered(
+        &mut self,
+        limit: Option<usize>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<G::Output>> {
+        let limit = limit.unwrap_or(usize::MAX).max(1);
+        loop {
+            // Start new expansions until the limit is reached or `f`
+            // has nothing ready.
+            let mut outer_pending = false;
+            while self.active.len() < limit {
+                let Some(outer) = &mut self.outer else {
+                    break;
+                };
+                match outer.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(b)) => self.active.push(Box::pin(self.g.apply(b))),
+                    Poll::Ready(None) => self.outer = None,
+                    Poll::Pending => {
+                        outer_pending = true;
+                        break;
+                    }
+                }
+            }
+            match self.active.poll_next_unpin(cx) {
+                Poll::Ready(Some(c)) => return Poll::Ready(Some(c)),
+                Poll::Ready(None) if self.outer.is_none() => return Poll::Ready(None),
+                Poll::Ready(None) => return Poll::Pending,
+                // finished expansions may have made room for `f`
+                Poll::Pending
+                    if !outer_pending && self.outer.is_some() && self.active.len() < limit =>
+                {
+                    continue
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<A, F: AsyncKleisli<A>, G: AsyncKleisli<F::Output>> Stream
+    for ApplyAsyncKleisliCompose<A, F, G>
+{
+    type Item = G::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match this.flatten {
+            Flatten::Sequential => this.poll_sequential(cx),
+            Flatten::Unordered(limit) => this.poll_unordered(limit, cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::{block_on, LocalPool};
+    use futures::stream::{self, BoxStream, LocalBoxStream};
+    use futures::task::LocalSpawnExt;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn lookup(x: usize) -> BoxStream<'static, usize> {
+        stream::iter(vec![x, x + 1]).boxed()
+    }
+
+    #[test]
+    fn sequential_preserves_order() {
+        let mut k = async_kleisli_compose(lookup, |y: usize| stream::iter(vec![y * 10, y * 100]));
+        let res: Vec<_> = block_on(k.apply(1).collect());
+        assert_eq!(res, vec![10, 100, 20, 200]);
+
+        let mut again = async_kleisli_compose(k, lookup);
+        let res: Vec<_> = block_on(again.apply(1).collect());
+        assert_eq!(res, vec![10, 11, 100, 101, 20, 21, 200, 201]);
+    }
+
+    // Returns to the executor once, asking to be polled again.
+    fn yield_now() -> impl std::future::Future<Output = ()> {
+        let mut yielded = false;
+        futures::future::poll_fn(move |cx| {
+            if yielded {
+                Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+    }
+
+    // An expansion which yields to the executor before each item,
+    // counting how many expansions are live at a time.
+    fn tracked(
+        live: Rc<Cell<usize>>,
+        most: Rc<Cell<usize>>,
+    ) -> impl FnMut(usize) -> LocalBoxStream<'static, usize> + Clone {
+        move |y| {
+            let (live, most) = (live.clone(), most.clone());
+            live.set(live.get() + 1);
+            most.set(most.get().max(live.get()));
+            stream::unfold(0, move |n| {
+                let live = live.clone();
+                async move {
+                    yield_now().await;
+                    if n < y {
+                        Some((y * 10 + n, n + 1))
+                    } else {
+                        live.set(live.get() - 1);
+                        None
+                    }
+                }
+            })
+            .boxed_local()
+        }
+    }
+
+    #[test]
+    fn unordered_bounds_concurrency() {
+        let live = Rc::new(Cell::new(0));
+        let most = Rc::new(Cell::new(0));
+        let mut k = async_kleisli_compose_unordered(
+            |x: usize| stream::iter(1..=x),
+            tracked(live.clone(), most.clone()),
+            2,
+        );
+        let mut res: Vec<_> = block_on(k.apply(4).collect());
+        res.sort();
+        assert_eq!(res, vec![10, 20, 21, 30, 31, 32, 40, 41, 42, 43]);
+        assert_eq!(most.get(), 2);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn unordered_on_local_pool() {
+        let live = Rc::new(Cell::new(0));
+        let most = Rc::new(Cell::new(0));
+        let out = Rc::new(Cell::new(0));
+        let mut k = async_kleisli_compose_unordered(
+            |x: usize| stream::iter(1..=x),
+            tracked(live.clone(), most.clone()),
+            None,
+        );
+        let mut pool = LocalPool::new();
+        let total = out.clone();
+        pool.spawner()
+            .spawn_local(async move {
+                total.set(k.apply(3).fold(0, |acc, x| async move { acc + x }).await);
+            })
+            .unwrap();
+        pool.run();
+        assert_eq!(out.get(), 10 + 20 + 21 + 30 + 31 + 32);
+        assert_eq!(most.get(), 3);
+    }
+}
@@ -0,0 +1,441 @@
+//! Path tracking for Kleisli arrows `A -> [A]`.
+//!
+//! A traced arrow yields each result together with the `Path` that
+//! witnesses it: the start node, then the values produced by every
+//! stage, in order, ending with the result itself. Paths are persistent
+//! linked lists, so the many results sharing a prefix share its storage.
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use crate::Kleisli;
+
+/// A persistent sequence of values, extended at the end.
+///
+/// Paths produced by this crate always begin with their start node, so
+/// the path of the start node itself has length one.
+pub struct Path<T>(Option<Rc<Step<T>>>);
+
+struct Step<T> {
+    last: T,
+    prev: Path<T>,
+    len: usize,
+}
+
+impl<T> Path<T> {
+    pub fn new() -> Self {
+        Path(None)
+    }
+
+    /// A path one value longer, sharing this one as its prefix.
+    pub fn push(&self, t: T) -> Self {
+        Path(Some(Rc::new(Step {
+            last: t,
+            prev: self.clone(),
+            len: self.len() + 1,
+        })))
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.0.as_ref().map(|step| &step.last)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.as_ref().map_or(0, |step| step.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// The values from last to first.
+    pub fn iter_rev(&self) -> impl Iterator<Item = &T> {
+        let mut path = self;
+        std::iter::from_fn(move || {
+            let step = path.0.as_ref()?;
+            path = &step.prev;
+            Some(&step.last)
+        })
+    }
+
+    /// The values from first to last.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut v: Vec<T> = self.iter_rev().cloned().collect();
+        v.reverse();
+        v
+    }
+}
+
+impl<T> Clone for Path<T> {
+    fn clone(&self) -> Self {
+        Path(self.0.clone())
+    }
+}
+
+// Unlink uniquely owned steps one at a time, rather than recursively,
+// so that dropping a long path cannot overflow the stack.
+impl<T> Drop for Path<T> {
+    fn drop(&mut self) {
+        let mut next = self.0.take();
+        while let Some(step) = next {
+            next = match Rc::try_unwrap(step) {
+                Ok(mut step) => step.prev.0.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl<T> Default for Path<T> {
+    fn default() -> Self {
+        Path::new()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Path<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter_rev().eq(other.iter_rev())
+    }
+}
+
+impl<T: Eq> Eq for Path<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for Path<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut v: Vec<&T> = self.iter_rev().collect();
+        v.reverse();
+        f.debug_list().entries(v).finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliTrace<F> {
+    f: F,
+}
+
+/// Lifts `f` to an arrow over traced values, extending the path of
+/// its input with each of its results. Start from `Path::new().push(a)`
+/// to include the start node `a`.
+pub fn kleisli_trace<A, F>(f: F) -> KleisliTrace<F>
+where
+    A: Clone,
+    F: Kleisli<A, Output = A>,
+{
+    KleisliTrace { f }
+}
+
+impl<A, F> Kleisli<(A, Path<A>)> for KleisliTrace<F>
+where
+    A: Clone,
+    F: Kleisli<A, Output = A>,
+{
+    type Output = (A, Path<A>);
+    type Iter = ApplyKleisliTrace<A, F>;
+
+    fn apply(&mut self, (a, path): (A, Path<A>)) -> Self::Iter {
+        ApplyKleisliTrace {
+            iter: self.f.apply(a),
+            path,
+        }
+    }
+}
+
+pub struct ApplyKleisliTrace<A, F: Kleisli<A>> {
+    iter: F::Iter,
+    path: Path<A>,
+}
+
+impl<A, F> Clone for ApplyKleisliTrace<A, F>
+where
+    F: Kleisli<A>,
+    F::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliTrace {
+            iter: self.iter.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<A: Clone, F: Kleisli<A, Output = A>> Iterator for ApplyKleisliTrace<A, F> {
+    type Item = (A, Path<A>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let b = self.iter.next()?;
+        let path = self.path.push(b.clone());
+        Some((b, path))
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliComposePath<F, G> {
+    f: F,
+    g: G,
+}
+
+/// Path-recording composition `f >=> g`: each result `c` of `g` comes
+/// paired with the result `b` of `f` it was reached through. Unlike the
+/// traced arrows, `f` and `g` may change the type of their argument.
+pub fn kleisli_compose_path<A, F, G>(f: F, g: G) -> KleisliComposePath<F, G>
+where
+    F: Kleisli<A>,
+    F::Output: Clone,
+    G: Kleisli<F::Output>,
+{
+    KleisliComposePath { f, g }
+}
+
+impl<A, F, G> Kleisli<A> for KleisliComposePath<F, G>
+where
+    F: Kleisli<A>,
+    F::Output: Clone,
+    G: Kleisli<F::Output> + Clone,
+{
+    type Output = (G::Output, F::Output);
+    type Iter = ApplyKleisliComposePath<A, F, G>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliComposePath {
+            outer: self.f.apply(a),
+            inner: None,
+            g: self.g.clone(),
+        }
+    }
+}
+
+pub struct ApplyKleisliComposePath<A, F: Kleisli<A>, G: Kleisli<F::Output>> {
+    outer: F::Iter,
+    inner: Option<(G::Iter, F::Output)>,
+    g: G,
+}
+
+impl<A, F, G> Clone for ApplyKleisliComposePath<A, F, G>
+where
+    F: Kleisli<A>,
+    F::Output: Clone,
+    G: Kleisli<F::Output> + Clone,
+    F::Iter: Clone,
+    G::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliComposePath {
+            outer: self.outer.clone(),
+            inner: self.inner.clone(),
+            g: self.g.clone(),
+        }
+    }
+}
+
+impl<A, F, G> Iterator for ApplyKleisliComposePath<A, F, G>
+where
+    F: Kleisli<A>,
+    F::Output: Clone,
+    G: Kleisli<F::Output>,
+{
+    type Item = (G::Output, F::Output);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((inner, b)) = &mut self.inner {
+                if let Some(c) = inner.next() {
+                    return Some((c, b.clone()));
+                }
+            }
+            let b = self.outer.next()?;
+            self.inner = Some((self.g.apply(b.clone()), b));
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliStarPath<F> {
+    f: F,
+    reflexive: bool,
+}
+
+/// `kleisli_star` yielding the path from the start node by which each
+/// node was first reached. The start node comes with the path `[start]`.
+pub fn kleisli_star_path<A, F>(f: F) -> KleisliStarPath<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    KleisliStarPath { f, reflexive: true }
+}
+
+/// `kleisli_plus` yielding the path by which each node was first reached.
+pub fn kleisli_plus_path<A, F>(f: F) -> KleisliStarPath<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    KleisliStarPath {
+        f,
+        reflexive: false,
+    }
+}
+
+impl<A, F> Kleisli<A> for KleisliStarPath<F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A> + Clone,
+{
+    type Output = (A, Path<A>);
+    type Iter = ApplyKleisliStarPath<A, F>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyKleisliStarPath::new(a, self.clone())
+    }
+}
+
+pub struct ApplyKleisliStarPath<A, F: Kleisli<A>> {
+    f: F,
+    visited: HashSet<A>,
+    stack: Vec<(F::Iter, Path<A>)>,
+    start: Option<A>,
+}
+
+impl<A, F> Clone for ApplyKleisliStarPath<A, F>
+where
+    A: Clone,
+    F: Kleisli<A> + Clone,
+    F::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyKleisliStarPath {
+            f: self.f.clone(),
+            visited: self.visited.clone(),
+            stack: self.stack.clone(),
+            start: self.start.clone(),
+        }
+    }
+}
+
+impl<A, F> ApplyKleisliStarPath<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    pub fn new(a: A, star: KleisliStarPath<F>) -> Self {
+        let KleisliStarPath { mut f, reflexive } = star;
+        let mut visited = HashSet::new();
+        let start = if reflexive {
+            visited.insert(a.clone());
+            Some(a.clone())
+        } else {
+            None
+        };
+        let stack = vec![(f.apply(a.clone()), Path::new().push(a))];
+        ApplyKleisliStarPath {
+            f,
+            visited,
+            stack,
+            start,
+        }
+    }
+}
+
+impl<A, F> Iterator for ApplyKleisliStarPath<A, F>
+where
+    A: Clone + Eq + Hash,
+    F: Kleisli<A, Output = A>,
+{
+    type Item = (A, Path<A>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(a) = self.start.take() {
+            return Some((a.clone(), Path::new().push(a)));
+        }
+        while let Some((top, path)) = self.stack.last_mut() {
+            match top.next() {
+                Some(b) => {
+                    if self.visited.insert(b.clone()) {
+                        let path = path.push(b.clone());
+                        self.stack.push((self.f.apply(b.clone()), path.clone()));
+                        return Some((b, path));
+                    }
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kleisli_compose;
+
+    const DB: &[(usize, usize)] = &[(1, 2), (2, 3), (3, 1), (3, 4), (1, 5), (5, 4)];
+
+    fn edges(x: usize) -> Vec<usize> {
+        DB.iter().filter(|e| e.0 == x).map(|e| e.1).collect()
+    }
+
+    #[test]
+    fn paths_share_prefixes() {
+        let p = Path::new().push(1).push(2);
+        let q = p.push(3);
+        let r = p.push(4);
+        assert_eq!(q.to_vec(), vec![1, 2, 3]);
+        assert_eq!(r.to_vec(), vec![1, 2, 4]);
+        assert_eq!(p.len(), 2);
+        assert_eq!(r.last(), Some(&4));
+        assert_eq!(format!("{q:?}"), "[1, 2, 3]");
+
+        let long = (0..1_000_000).fold(Path::new(), |p, i| p.push(i));
+        assert_eq!(long.len(), 1_000_000);
+        drop(long);
+    }
+
+    #[test]
+    fn compose_path_records_each_stage() {
+        let mut k = kleisli_compose_path(edges, edges);
+        assert_eq!(k.apply(1).collect::<Vec<_>>(), vec![(3, 2), (4, 5)]);
+
+        let mut named = kleisli_compose_path(edges, |x: usize| vec![format!("n{x}")]);
+        let res: Vec<(String, usize)> = named.apply(3).collect();
+        assert_eq!(res, vec![("n1".to_string(), 1), ("n4".to_string(), 4)]);
+    }
+
+    #[test]
+    fn traced_arrows_chain() {
+        let mut k = kleisli_compose(
+            kleisli_compose(kleisli_trace(edges), kleisli_trace(edges)),
+            kleisli_trace(edges),
+        );
+        let res: Vec<_> = k
+            .apply((1, Path::new().push(1)))
+            .map(|(x, p)| (x, p.to_vec()))
+            .collect();
+        assert_eq!(res, vec![(1, vec![1, 2, 3, 1]), (4, vec![1, 2, 3, 4])]);
+    }
+
+    #[test]
+    fn closure_paths_witness_reachability() {
+        let mut star = kleisli_star_path(edges);
+        let res: Vec<_> = star.apply(1).map(|(x, p)| (x, p.to_vec())).collect();
+        assert_eq!(
+            res,
+            vec![
+                (1, vec![1]),
+                (2, vec![1, 2]),
+                (3, vec![1, 2, 3]),
+                (4, vec![1, 2, 3, 4]),
+                (5, vec![1, 5]),
+            ]
+        );
+
+        let mut plus = kleisli_plus_path(edges);
+        let back: Vec<_> = plus.apply(3).filter(|(x, _)| *x == 3).collect();
+        assert_eq!(back, vec![(3, Path::new().push(3).push(1).push(2).push(3))]);
+    }
+}
@@ -0,0 +1,519 @@
+//! Weighted Kleisli arrows over semirings.
+//!
+//! A weighted arrow yields `(B, W)` pairs. Composing weighted arrows
+//! multiplies the weights along each route, and aggregating sums the
+//! weights of the routes reaching the same output, so the choice of
+//! semiring decides what is computed: the cheapest route, the most
+//! probable one, whether there is one, or how many there are.
+//!
+//! When the weights are also ordered, `kleisli_dijkstra` and
+//! `kleisli_astar` give the fixed point: every reachable node in order
+//! of the cost of its best route.
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::Kleisli;
+
+/// A semiring: `plus` combines alternative routes, `times` extends a
+/// route, `zero` is the weight of no route and `one` of the empty one.
+pub trait Semiring: Clone {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn plus(&self, other: &Self) -> Self;
+    fn times(&self, other: &Self) -> Self;
+}
+
+/// The min-plus semiring of route costs. Costs are totally ordered,
+/// cheapest first, by `f64::total_cmp`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tropical(pub f64);
+
+impl PartialEq for Tropical {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Tropical {}
+
+impl PartialOrd for Tropical {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Tropical {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Semiring for Tropical {
+    fn zero() -> Self {
+        Tropical(f64::INFINITY)
+    }
+
+    fn one() -> Self {
+        Tropical(0.0)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Tropical(self.0.min(other.0))
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Tropical(self.0 + other.0)
+    }
+}
+
+/// The or-and semiring of reachability.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Boolean(pub bool);
+
+impl Semiring for Boolean {
+    fn zero() -> Self {
+        Boolean(false)
+    }
+
+    fn one() -> Self {
+        Boolean(true)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Boolean(self.0 || other.0)
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Boolean(self.0 && other.0)
+    }
+}
+
+/// The natural numbers, counting routes. Route counts grow
+/// exponentially with path length, so both operations saturate at
+/// `u64::MAX` rather than overflow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Counting(pub u64);
+
+impl Semiring for Counting {
+    fn zero() -> Self {
+        Counting(0)
+    }
+
+    fn one() -> Self {
+        Counting(1)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Counting(self.0.saturating_add(other.0))
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Counting(self.0.saturating_mul(other.0))
+    }
+}
+
+/// The max-times (Viterbi) semiring of probabilities, keeping the
+/// most probable route.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Probability(pub f64);
+
+impl Semiring for Probability {
+    fn zero() -> Self {
+        Probability(0.0)
+    }
+
+    fn one() -> Self {
+        Probability(1.0)
+    }
+
+    fn plus(&self, other: &Self) -> Self {
+        Probability(self.0.max(other.0))
+    }
+
+    fn times(&self, other: &Self) -> Self {
+        Probability(self.0 * other.0)
+    }
+}
+
+#[derive(Clone)]
+pub struct WeightedKleisliCompose<F, G> {
+    f: F,
+    g: G,
+}
+
+/// Composition of weighted arrows: each result of `g` is weighted by
+/// the product of its own weight and that of the result of `f` it was
+/// reached through.
+pub fn weighted_kleisli_compose<A, B, C, W, F, G>(f: F, g: G) -> WeightedKleisliCompose<F, G>
+where
+    W: Semiring,
+    F: Kleisli<A, Output = (B, W)>,
+    G: Kleisli<B, Output = (C, W)>,
+{
+    WeightedKleisliCompose { f, g }
+}
+
+impl<A, B, C, W, F, G> Kleisli<A> for WeightedKleisliCompose<F, G>
+where
+    W: Semiring,
+    F: Kleisli<A, Output = (B, W)>,
+    G: Kleisli<B, Output = (C, W)> + Clone,
+{
+    type Output = (C, W);
+    type Iter = ApplyWeightedKleisliCompose<A, B, W, F, G>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        ApplyWeightedKleisliCompose {
+            outer: self.f.apply(a),
+            inner: None,
+            g: self.g.clone(),
+        }
+    }
+}
+
+pub struct ApplyWeightedKleisliCompose<A, B, W, F: Kleisli<A>, G: Kleisli<B>> {
+    outer: F::Iter,
+    inner: Option<(G::Iter, W)>,
+    g: G,
+}
+
+impl<A, B, W, F, G> Clone for ApplyWeightedKleisliCompose<A, B, W, F, G>
+where
+    W: Clone,
+    F: Kleisli<A>,
+    G: Kleisli<B> + Clone,
+    F::Iter: Clone,
+    G::Iter: Clone,
+{
+    fn clone(&self) -> Self {
+        ApplyWeightedKleisliCompose {
+            outer: self.outer.clone(),
+            inner: self.inner.clone(),
+            g: self.g.clone(),
+        }
+    }
+}
+
+impl<A, B, C, W, F, G> Iterator for ApplyWeightedKleisliCompose<A, B, W, F, G>
+where
+    W: Semiring,
+    F: Kleisli<A, Output = (B, W)>,
+    G: Kleisli<B, Output = (C, W)>,
+{
+    type Item = (C, W);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((inner, w)) = &mut self.inner {
+                if let Some((c, v)) = inner.next() {
+                    return Some((c, w.times(&v)));
+                }
+            }
+            let (b, w) = self.outer.next()?;
+            self.inner = Some((self.g.apply(b), w));
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct KleisliAggregate<F> {
+    f: F,
+}
+
+/// Sums, with `plus`, the weights of all results of `f` for the same
+/// output, yielding each output once in order of first appearance.
+/// The results of each application are gathered before any is yielded.
+pub fn kleisli_aggregate<A, B, W, F>(f: F) -> KleisliAggregate<F>
+where
+    B: Hash + Eq + Clone,
+    W: Semiring,
+    F: Kleisli<A, Output = (B, W)>,
+{
+    KleisliAggregate { f }
+}
+
+impl<A, B, W, F> Kleisli<A> for KleisliAggregate<F>
+where
+    B: Hash + Eq + Clone,
+    W: Semiring,
+    F: Kleisli<A, Output = (B, W)>,
+{
+    type Output = (B, W);
+    type Iter = std::vec::IntoIter<(B, W)>;
+
+    fn apply(&mut self, a: A) -> Self::Iter {
+        let mut index: HashMap<B, usize> = HashMap::new();
+        let mut sums: Vec<(B, W)> = Vec::new();
+        for (b, w) in self.f.apply(a) {
+            match index.get(&b) {
+                Some(&i) => sums[i].1 = sums[i].1.plus(&w),
+                None => {
+                    index.insert(b.clone(), sums.len());
+                    sums.push((b, w));
+                }
+            }
+        }
+        sums.into_iter()
+    }
+}
+
+/// Dijkstra's algorithm over a weighted arrow: every node reachable
+/// from `start`, each once, with the weight of its best route, in
+/// non-decreasing order of that weight.
+///
+/// The order on `W` ranks better weights first, and extending a route
+/// with `times` must never make it better, as with non-negative costs
+/// in the `Tropical` semiring.
+pub fn kleisli_dijkstra<A, W, F>(f: F, start: A) -> KleisliDijkstra<A, W, F, fn(&A) -> W>
+where
+    A: Clone + Eq + Hash,
+    W: Semiring + Ord,
+    F: Kleisli<A, Output = (A, W)>,
+{
+    kleisli_astar(f, start, |_| W::one())
+}
+
+/// A* search: Dijkstra's algorithm with routes ranked by their weight
+/// times the estimate `h` of the weight still to go. Nodes are yielded
+/// in order of that estimate, each with the weight of its route, which
+/// is the best one as long as `h` never overestimates and is
+/// consistent across edges.
+pub fn kleisli_astar<A, W, F, H>(f: F, start: A, mut h: H) -> KleisliDijkstra<A, W, F, H>
+where
+    A: Clone + Eq + Hash,
+    W: Semiring + Ord,
+    F: Kleisli<A, Output = (A, W)>,
+    H: FnMut(&A) -> W,
+{
+    let mut heap = BinaryHeap::new();
+    heap.push(Frontier {
+        rank: W::one().times(&h(&start)),
+        seq: 0,
+        cost: W::one(),
+        node: start,
+    });
+    KleisliDijkstra {
+        f,
+        h,
+        heap,
+        settled: HashSet::new(),
+        seq: 1,
+        expand: None,
+    }
+}
+
+// A route waiting in the queue. The heap is a max-heap, so the order
+// is reversed to pop the best rank first, oldest first among equals.
+#[derive(Clone)]
+struct Frontier<A, W> {
+    rank: W,
+    seq: u64,
+    cost: W,
+    node: A,
+}
+
+impl<A, W: Ord> PartialEq for Frontier<A, W> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<A, W: Ord> Eq for Frontier<A, W> {}
+
+impl<A, W: Ord> PartialOrd for Frontier<A, W> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<A, W: Ord> Ord for Frontier<A, W> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .rank
+            .cmp(&self.rank)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+pub struct KleisliDijkstra<A, W, F, H> {
+    f: F,
+    h: H,
+    heap: BinaryHeap<Frontier<A, W>>,
+    settled: HashSet<A>,
+    seq: u64,
+    expand: Option<(A, W)>,
+}
+
+impl<A, W, F, H> Clone for KleisliDijkstra<A, W, F, H>
+where
+    A: Clone,
+    W: Clone,
+    F: Clone,
+    H: Clone,
+{
+    fn clone(&self) -> Self {
+        KleisliDijkstra {
+            f: self.f.clone(),
+            h: self.h.clone(),
+            heap: self.heap.clone(),
+            settled: self.settled.clone(),
+            seq: self.seq,
+            expand: self.expand.clone(),
+        }
+    }
+}
+
+impl<A, W, F, H> Iterator for KleisliDijkstra<A, W, F, H>
+where
+    A: Clone + Eq + Hash,
+    W: Semiring + Ord,
+    F: Kleisli<A, Output = (A, W)>,
+    H: FnMut(&A) -> W,
+{
+    type Item = (A, W);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the edges of the last node yielded are only looked up now
+        if let Some((a, cost)) = self.expand.take() {
+            for (b, w) in self.f.apply(a) {
+                if !self.settled.contains(&b) {
+                    let cost = cost.times(&w);
+                    self.heap.push(Frontier {
+                        rank: cost.times(&(self.h)(&b)),
+                        seq: self.seq,
+                        cost,
+                        node: b,
+                    });
+                    self.seq += 1;
+                }
+            }
+        }
+        while let Some(Frontier { cost, node, .. }) = self.heap.pop() {
+            if self.settled.insert(node.clone()) {
+                self.expand = Some((node.clone(), cost.clone()));
+                return Some((node, cost));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A diamond with a shortcut: 1 -> 2 -> 4, 1 -> 3 -> 4, 1 -> 4.
+    const ROADS: &[(usize, usize, f64)] = &[
+        (1, 2, 1.0),
+        (1, 3, 2.0),
+        (2, 4, 5.0),
+        (3, 4, 1.0),
+        (1, 4, 10.0),
+        (4, 5, 0.5),
+    ];
+
+    fn weighted<W>(weight: impl Fn(f64) -> W + Clone) -> impl Fn(usize) -> Vec<(usize, W)> + Clone {
+        move |x| {
+            ROADS
+                .iter()
+                .filter(|e| e.0 == x)
+                .map(|e| (e.1, weight(e.2)))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn compose_multiplies_along_routes() {
+        let mut two = weighted_kleisli_compose(weighted(Tropical), weighted(Tropical));
+        assert_eq!(
+            two.apply(1).collect::<Vec<_>>(),
+            vec![(4, Tropical(6.0)), (4, Tropical(3.0)), (5, Tropical(10.5))]
+        );
+    }
+
+    #[test]
+    fn aggregate_min_cost() {
+        let mut cheapest = kleisli_aggregate(weighted_kleisli_compose(
+            weighted(Tropical),
+            weighted(Tropical),
+        ));
+        assert_eq!(
+            cheapest.apply(1).collect::<Vec<_>>(),
+            vec![(4, Tropical(3.0)), (5, Tropical(10.5))]
+        );
+    }
+
+    #[test]
+    fn aggregate_counts_and_reachability() {
+        let mut count = kleisli_aggregate(weighted_kleisli_compose(
+            weighted(|_| Counting(1)),
+            weighted(|_| Counting(1)),
+        ));
+        assert_eq!(
+            count.apply(1).collect::<Vec<_>>(),
+            vec![(4, Counting(2)), (5, Counting(1))]
+        );
+
+        let mut reach = kleisli_aggregate(weighted_kleisli_compose(
+            weighted(|_| Boolean(true)),
+            weighted(|_| Boolean(true)),
+        ));
+        assert_eq!(reach.apply(2).collect::<Vec<_>>(), vec![(5, Boolean(true))]);
+    }
+
+    #[test]
+    fn most_probable_route() {
+        let mut likely = kleisli_aggregate(weighted_kleisli_compose(
+            weighted(|w| Probability(1.0 / (1.0 + w))),
+            weighted(|w| Probability(1.0 / (1.0 + w))),
+        ));
+        let res: Vec<_> = likely.apply(1).collect();
+        assert_eq!(res[0], (4, Probability(1.0 / 6.0)));
+    }
+
+    #[test]
+    fn dijkstra_yields_in_cost_order() {
+        let reached: Vec<_> = kleisli_dijkstra(weighted(Tropical), 1).collect();
+        assert_eq!(
+            reached,
+            vec![
+                (1, Tropical(0.0)),
+                (2, Tropical(1.0)),
+                (3, Tropical(2.0)),
+                (4, Tropical(3.0)),
+                (5, Tropical(3.5)),
+            ]
+        );
+        assert_eq!(kleisli_dijkstra(weighted(Tropical), 5).count(), 1);
+    }
+
+    #[test]
+    fn astar_settles_fewer_nodes() {
+        // the remaining cost to 5 by the best route from each node
+        let to_go = |x: &usize| Tropical([0.0, 3.5, 5.5, 1.5, 0.5, 0.0][*x]);
+        let mut expanded = Vec::new();
+        let lookup = |x: usize| {
+            expanded.push(x);
+            weighted(Tropical)(x)
+        };
+        let found = kleisli_astar(lookup, 1, to_go).find(|(x, _)| *x == 5);
+        assert_eq!(found, Some((5, Tropical(3.5))));
+        assert_eq!(expanded, vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn semiring_units() {
+        let w = Tropical(4.0);
+        assert_eq!(w.plus(&Tropical::zero()), w);
+        assert_eq!(w.times(&Tropical::one()), w);
+        assert_eq!(Counting(3).times(&Counting::zero()), Counting::zero());
+        let many = Counting(u64::MAX / 2);
+        assert_eq!(many.times(&Counting(3)), Counting(u64::MAX));
+        assert_eq!(many.plus(&many).plus(&many), Counting(u64::MAX));
+        assert_eq!(
+            Probability(0.5).times(&Probability::one()),
+            Probability(0.5)
+        );
+    }
+}
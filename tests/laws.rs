@@ -0,0 +1,37 @@
+//! The monad laws for `kleisli_compose` with `ret` as unit, checked
+//! over arrows looking up edges in randomly generated graphs.
+use kleisli::{kleisli_compose, ret, Kleisli};
+use proptest::prelude::*;
+
+type Edges = Vec<(u8, u8)>;
+
+fn lookup(db: Edges) -> impl Fn(u8) -> Vec<u8> + Clone {
+    move |x| db.iter().filter(|e| e.0 == x).map(|e| e.1).collect()
+}
+
+fn run<K: Kleisli<u8, Output = u8>>(mut k: K, a: u8) -> Vec<u8> {
+    k.apply(a).collect()
+}
+
+fn edges() -> impl Strategy<Value = Edges> {
+    prop::collection::vec((0..8u8, 0..8u8), 0..24)
+}
+
+proptest! {
+    #[test]
+    fn left_identity(db in edges(), a in 0..8u8) {
+        prop_assert_eq!(run(kleisli_compose(ret, lookup(db.clone())), a), run(lookup(db), a));
+    }
+
+    #[test]
+    fn right_identity(db in edges(), a in 0..8u8) {
+        prop_assert_eq!(run(kleisli_compose(lookup(db.clone()), ret), a), run(lookup(db), a));
+    }
+
+    #[test]
+    fn associativity(f in edges(), g in edges(), h in edges(), a in 0..8u8) {
+        let left = kleisli_compose(kleisli_compose(lookup(f.clone()), lookup(g.clone())), lookup(h.clone()));
+        let right = kleisli_compose(lookup(f), kleisli_compose(lookup(g), lookup(h)));
+        prop_assert_eq!(run(left, a), run(right, a));
+    }
+}